@@ -0,0 +1,84 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use qrcode::{EcLevel, QrCode, Version};
+use qrterm::image::{Dot, HalfPoint, Image, Point};
+use std::hint::black_box;
+use std::io::{self, Write};
+
+/// The previous image layout with a `Vec` of points per line, kept for
+/// comparing against the flat layout.
+struct NestedImage {
+    lines: Vec<Vec<Point>>,
+    last_line: Option<Vec<HalfPoint>>,
+}
+
+impl NestedImage {
+    fn from_dots(dots: &[Dot], width: usize) -> Self {
+        let mut lines = Vec::new();
+        let mut last_line = None;
+        for line in dots.chunks(width * 2) {
+            if line.len() == width * 2 {
+                lines.push(
+                    line[..width]
+                        .iter()
+                        .zip(&line[width..])
+                        .map(|(&top, &bot)| Point { top, bot })
+                        .collect(),
+                );
+            } else {
+                last_line = Some(line.iter().copied().map(HalfPoint).collect());
+            }
+        }
+        NestedImage { lines, last_line }
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for line in &self.lines {
+            for point in line {
+                write!(out, "{}", point.to_char())?;
+            }
+            writeln!(out)?;
+        }
+        if let Some(last_line) = &self.last_line {
+            for point in last_line {
+                write!(out, "{}", point.to_char())?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let code = QrCode::with_version(b"qrterm", Version::Normal(40), EcLevel::L).unwrap();
+    let image = code.render::<Dot>().build();
+    let (width, _) = image.dimensions();
+    let dots = image.to_dots();
+
+    c.bench_function("build version 40", |b| {
+        b.iter(|| black_box(&code).render::<Dot>().build())
+    });
+    c.bench_function("pack flat version 40", |b| {
+        b.iter(|| Image::from_dots(black_box(&dots), width))
+    });
+    c.bench_function("pack nested version 40", |b| {
+        b.iter(|| NestedImage::from_dots(black_box(&dots), width))
+    });
+
+    let nested = NestedImage::from_dots(&dots, width);
+    let mut buffer = Vec::new();
+    c.bench_function("write flat version 40", |b| {
+        b.iter(|| {
+            buffer.clear();
+            black_box(&image).write_to(&mut buffer).unwrap();
+        })
+    });
+    c.bench_function("write nested version 40", |b| {
+        b.iter(|| {
+            buffer.clear();
+            black_box(&nested).write_to(&mut buffer).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
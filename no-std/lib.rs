@@ -0,0 +1,12 @@
+//! Build check that the core rendering logic compiles under `no_std` with
+//! only `alloc`. The modules are included directly from the `qrterm` sources,
+//! so building this crate fails if they start depending on `std`.
+
+#![no_std]
+
+extern crate alloc;
+
+#[path = "../src/color.rs"]
+pub mod color;
+#[path = "../src/core.rs"]
+pub mod core;
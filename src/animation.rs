@@ -0,0 +1,157 @@
+//! Animated GIF output for cycling through a sequence of QR codes.
+
+use crate::image::{Dot, Image};
+use gif::{Encoder, Frame, Repeat};
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Options for writing an animated GIF.
+#[derive(Clone, Copy, Debug)]
+pub struct GifOptions {
+    /// The size of each QR dot in pixels.
+    pub scale: u16,
+    /// The delay between frames in milliseconds. GIF delays have a resolution
+    /// of 10 milliseconds, so this is rounded down accordingly.
+    pub delay_ms: u32,
+    /// The number of times to repeat the animation, or `None` to loop forever.
+    pub loops: Option<u16>,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            scale: 4,
+            delay_ms: 1000,
+            loops: None,
+        }
+    }
+}
+
+/// Writes the images as frames of an animated GIF.
+///
+/// All frames share the dimensions of the largest image, with smaller images
+/// drawn in the top-left corner and padded with white dots.
+pub fn write_gif<W: Write>(images: &[Image], options: &GifOptions, out: W) -> io::Result<()> {
+    // Palette indices for white and black dots respectively.
+    const PALETTE: &[u8] = &[0xff, 0xff, 0xff, 0x00, 0x00, 0x00];
+
+    let scale = options.scale.max(1) as usize;
+    let frames = images
+        .iter()
+        .map(|image| {
+            let (columns, _) = image.dimensions();
+            let dots = image.to_dots();
+            let rows = dots.len().checked_div(columns).unwrap_or(0);
+            (columns, rows, dots)
+        })
+        .collect::<Vec<_>>();
+    let columns = frames.iter().map(|frame| frame.0).max().unwrap_or(0);
+    let rows = frames.iter().map(|frame| frame.1).max().unwrap_or(0);
+    let (width, height) = (columns * scale, rows * scale);
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "image too large for GIF",
+        ));
+    }
+
+    let mut encoder =
+        Encoder::new(out, width as u16, height as u16, PALETTE).map_err(encoding_error)?;
+    encoder
+        .set_repeat(match options.loops {
+            Some(loops) => Repeat::Finite(loops),
+            None => Repeat::Infinite,
+        })
+        .map_err(encoding_error)?;
+
+    let delay = (options.delay_ms / 10).min(u16::MAX as u32) as u16;
+    for (frame_columns, _, dots) in &frames {
+        let mut pixels = vec![0; width * height];
+        for (row, line) in dots.chunks((*frame_columns).max(1)).enumerate() {
+            for (column, dot) in line.iter().enumerate() {
+                let index = match dot {
+                    Dot::White => 0,
+                    Dot::Black => 1,
+                };
+                for y in row * scale..(row + 1) * scale {
+                    pixels[y * width + column * scale..][..scale].fill(index);
+                }
+            }
+        }
+
+        encoder
+            .write_frame(&Frame {
+                width: width as u16,
+                height: height as u16,
+                delay,
+                buffer: Cow::Owned(pixels),
+                ..Frame::default()
+            })
+            .map_err(encoding_error)?;
+    }
+
+    Ok(())
+}
+
+/// Converts a GIF encoding error into an I/O error, unwrapping I/O failures.
+fn encoding_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gif::{ColorOutput, DecodeOptions};
+    use Dot::{Black as B, White as W};
+
+    #[test]
+    fn write_gif_frames() {
+        let images = [
+            Image::from_dots(&[B, W, W, B], 2),
+            Image::from_dots(&[B, B, B, W, W, W, B, B, B], 3),
+        ];
+        let options = GifOptions {
+            scale: 2,
+            delay_ms: 250,
+            loops: Some(3),
+        };
+        let mut out = Vec::new();
+        write_gif(&images, &options, &mut out).unwrap();
+
+        let mut decode = DecodeOptions::new();
+        decode.set_color_output(ColorOutput::Indexed);
+        let mut decoder = decode.read_info(&out[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 6));
+        assert_eq!(decoder.repeat(), Repeat::Finite(3));
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 25);
+            frames.push(frame.buffer.to_vec());
+        }
+        assert_eq!(
+            frames,
+            [
+                vec![
+                    1, 1, 0, 0, 0, 0, //
+                    1, 1, 0, 0, 0, 0, //
+                    0, 0, 1, 1, 0, 0, //
+                    0, 0, 1, 1, 0, 0, //
+                    0, 0, 0, 0, 0, 0, //
+                    0, 0, 0, 0, 0, 0, //
+                ],
+                vec![
+                    1, 1, 1, 1, 1, 1, //
+                    1, 1, 1, 1, 1, 1, //
+                    0, 0, 0, 0, 0, 0, //
+                    0, 0, 0, 0, 0, 0, //
+                    1, 1, 1, 1, 1, 1, //
+                    1, 1, 1, 1, 1, 1, //
+                ],
+            ]
+        );
+    }
+}
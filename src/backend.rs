@@ -0,0 +1,808 @@
+//! Output backends for writing rendered QR code images in different formats,
+//! either as files for embedding in documents or printing, or as inline
+//! images for terminals supporting a graphics protocol. Text output is
+//! rendered by [`Image`] itself.
+
+use crate::error::{QrTermError, Result};
+use crate::image::{Dot, Image};
+use ::image::codecs::png::PngEncoder;
+use ::image::{ExtendedColorType, ImageEncoder};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A backend for writing a rendered QR code image in a specific format.
+pub trait Backend {
+    /// Writes the image to the writer.
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Unpacks an image into rows of dots, returning the dots along with the
+/// width and height of the image in dots.
+fn dots(image: &Image) -> (Vec<Dot>, usize, usize) {
+    let (width, _) = image.dimensions();
+    let dots = image.to_dots();
+    let height = dots.len().checked_div(width).unwrap_or(0);
+    (dots, width, height)
+}
+
+/// How to draw the finder patterns in the corners of a QR code.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FinderStyle {
+    /// Concentric filled 7x7, 5x5 and 3x3 squares.
+    #[default]
+    Solid,
+    /// A 7x7 outline one module thick around a filled 3x3 square.
+    Outline,
+}
+
+impl FromStr for FinderStyle {
+    type Err = QrTermError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "solid" => Ok(FinderStyle::Solid),
+            "outline" => Ok(FinderStyle::Outline),
+            _ => Err(QrTermError::InvalidOptions(format!(
+                "invalid finder style '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// A logo image overlaid in the centre of PNG and SVG output, drawn at its
+/// original size in pixels.
+#[derive(Clone, Debug)]
+pub struct Logo {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    png: Vec<u8>,
+}
+
+impl Logo {
+    /// Loads a logo from an encoded image file, such as a PNG or JPEG.
+    pub fn load(bytes: &[u8]) -> Result<Logo> {
+        let invalid = |err: ::image::ImageError| {
+            QrTermError::InvalidOptions(format!("invalid logo image: {}", err))
+        };
+        let image = ::image::load_from_memory(bytes)
+            .map_err(invalid)?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        let rgba = image.into_raw();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(&rgba, width, height, ExtendedColorType::Rgba8)
+            .map_err(invalid)?;
+        Ok(Logo {
+            width,
+            height,
+            rgba,
+            png,
+        })
+    }
+
+    /// Returns the top-left pixel of the logo centred in an image of `width`
+    /// by `height` pixels.
+    fn origin(&self, width: usize, height: usize) -> (usize, usize) {
+        (
+            width.saturating_sub(self.width as usize) / 2,
+            height.saturating_sub(self.height as usize) / 2,
+        )
+    }
+
+    /// Returns the rectangle of dots covered by the logo when it is centred on
+    /// the image drawn with `scale` pixels per dot, as `(x, y, w, h)`.
+    pub fn covered_dots(&self, image: &Image, scale: u32) -> (usize, usize, usize, usize) {
+        let (_, width, height) = dots(image);
+        let scale = scale.max(1) as usize;
+        let (x, y) = self.origin(width * scale, height * scale);
+        let right = (x + self.width as usize).div_ceil(scale).min(width);
+        let bottom = (y + self.height as usize).div_ceil(scale).min(height);
+        (x / scale, y / scale, right - x / scale, bottom - y / scale)
+    }
+
+    /// Clears the dots covered by the logo to white, so that the modules
+    /// underneath do not show through transparent parts of the logo.
+    pub fn knock_out(&self, image: &Image, scale: u32) -> Image {
+        let (x, y, w, h) = self.covered_dots(image, scale);
+        image.fill(x, y, w, h, Dot::White)
+    }
+
+    /// Blends the logo over the centre of RGBA pixels of an image that is
+    /// `width` pixels wide.
+    fn overlay(&self, pixels: &mut [u8], width: usize) {
+        let height = pixels.len() / 4 / width.max(1);
+        let (x, y) = self.origin(width, height);
+        let rows = self.rgba.chunks(self.width as usize * 4).take(height - y);
+        for (dy, row) in rows.enumerate() {
+            let line =
+                &mut pixels[((y + dy) * width + x) * 4..][..(width - x).min(row.len() / 4) * 4];
+            for (dst, src) in line.chunks_mut(4).zip(row.chunks(4)) {
+                let alpha = src[3] as u32;
+                for c in 0..3 {
+                    dst[c] = ((src[c] as u32 * alpha + dst[c] as u32 * (255 - alpha)) / 255) as u8;
+                }
+                dst[3] = (alpha + dst[3] as u32 * (255 - alpha) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Writes images as SVG documents, with one user unit per dot.
+#[derive(Clone, Debug)]
+pub struct Svg {
+    /// The displayed size of each dot in pixels.
+    pub scale: u32,
+    /// Whether to leave white dots transparent instead of drawing a white
+    /// background.
+    pub transparent: bool,
+    /// How to draw the finder patterns. Images without black finder patterns
+    /// in their corners are drawn dot by dot.
+    pub finder_style: FinderStyle,
+    /// A logo to embed in the centre of the image.
+    pub logo: Option<Logo>,
+}
+
+impl Backend for Svg {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let (dots, width, height) = dots(image);
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+            width as u32 * self.scale,
+            height as u32 * self.scale,
+            width,
+            height,
+        )?;
+        if !self.transparent {
+            writeln!(out, r##"<rect width="100%" height="100%" fill="#fff"/>"##)?;
+        }
+        let (finders, module) = finders(&dots, width, height);
+        let in_finder = |x: usize, y: usize| {
+            finders.iter().any(|&(fx, fy)| {
+                (fx..fx + 7 * module).contains(&x) && (fy..fy + 7 * module).contains(&y)
+            })
+        };
+        write!(out, r##"<path fill="#000" d=""##)?;
+        for (i, dot) in dots.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            if let Dot::Black = dot {
+                if !in_finder(x, y) {
+                    write!(out, "M{} {}h1v1h-1z", x, y)?;
+                }
+            }
+        }
+        writeln!(out, r#""/>"#)?;
+
+        let m = module;
+        for &(x, y) in &finders {
+            match self.finder_style {
+                FinderStyle::Solid => {
+                    let square = |out: &mut dyn Write, inset: usize, fill: &str| {
+                        let size = (7 - 2 * inset) * m;
+                        writeln!(
+                            out,
+                            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                            x + inset * m,
+                            y + inset * m,
+                            size,
+                            size,
+                            fill,
+                        )
+                    };
+                    if self.transparent {
+                        // Cut the light ring out of the outer square with the
+                        // even-odd rule, so that the background shows through.
+                        writeln!(
+                            out,
+                            r##"<path fill="#000" fill-rule="evenodd" d="M{} {}h{}v{}h-{}zM{} {}h{}v{}h-{}z"/>"##,
+                            x,
+                            y,
+                            7 * m,
+                            7 * m,
+                            7 * m,
+                            x + m,
+                            y + m,
+                            5 * m,
+                            5 * m,
+                            5 * m,
+                        )?;
+                    } else {
+                        square(out, 0, "#000")?;
+                        square(out, 1, "#fff")?;
+                    }
+                    square(out, 2, "#000")?;
+                }
+                FinderStyle::Outline => {
+                    // The stroke is centred on the outline of the rectangle,
+                    // so inset it by half a module to keep it inside the 7x7
+                    // pattern.
+                    let half = m as f64 / 2.0;
+                    writeln!(
+                        out,
+                        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#000" stroke-width="{}"/>"##,
+                        x as f64 + half,
+                        y as f64 + half,
+                        6 * m,
+                        6 * m,
+                        m,
+                    )?;
+                    writeln!(
+                        out,
+                        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#000"/>"##,
+                        x + 2 * m,
+                        y + 2 * m,
+                        3 * m,
+                        3 * m,
+                    )?;
+                }
+            }
+        }
+        if let Some(logo) = &self.logo {
+            let scale = self.scale.max(1) as f64;
+            let (x, y) = logo.origin(
+                width * self.scale.max(1) as usize,
+                height * self.scale.max(1) as usize,
+            );
+            writeln!(
+                out,
+                r#"<image x="{}" y="{}" width="{}" height="{}" href="data:image/png;base64,{}"/>"#,
+                x as f64 / scale,
+                y as f64 / scale,
+                logo.width as f64 / scale,
+                logo.height as f64 / scale,
+                BASE64.encode(&logo.png),
+            )?;
+        }
+        writeln!(out, "</svg>")
+    }
+}
+
+/// Locates the finder patterns in the corners of a QR code image, returning
+/// the top-left dot of each along with the size of a module in dots. No
+/// finders are returned if the corners of the black dots do not contain the
+/// 7x7 module finder pattern, for example if dark modules are white.
+fn finders(dots: &[Dot], width: usize, height: usize) -> (Vec<(usize, usize)>, usize) {
+    let black = |x: usize, y: usize| dots[x + y * width] == Dot::Black;
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in (0..width).filter(|&x| black(x, y)) {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+    }
+    if left >= right {
+        return (Vec::new(), 0);
+    }
+
+    let run = (left..right).take_while(|&x| black(x, top)).count();
+    let module = run / 7;
+    if module == 0 || run % 7 != 0 || right - left < 7 * module || bottom - top < 7 * module {
+        return (Vec::new(), 0);
+    }
+
+    let is_finder = |fx: usize, fy: usize| {
+        (0..7 * module).all(|dy| {
+            (0..7 * module).all(|dx| {
+                let ring = (dx / module).abs_diff(3).max((dy / module).abs_diff(3));
+                black(fx + dx, fy + dy) == (ring != 2)
+            })
+        })
+    };
+    let (far_x, far_y) = (right - 7 * module, bottom - 7 * module);
+    let finders = [(left, top), (far_x, top), (left, far_y), (far_x, far_y)]
+        .iter()
+        .copied()
+        .filter(|&(x, y)| is_finder(x, y))
+        .collect();
+    (finders, module)
+}
+
+/// Writes images as grayscale PNG images, or as RGBA images with a logo.
+#[derive(Clone, Debug)]
+pub struct Png {
+    /// The size of each dot in pixels.
+    pub scale: u32,
+    /// Whether to add an alpha channel in which white dots are fully
+    /// transparent.
+    pub transparent: bool,
+    /// A logo to overlay in the centre of the image.
+    pub logo: Option<Logo>,
+}
+
+impl Backend for Png {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let (pixels, width, height) = luma(image, self.scale);
+        let (pixels, color_type) = if self.transparent {
+            let pixels = pixels
+                .into_iter()
+                .flat_map(|luma| [luma, if luma == 0xff { 0x00 } else { 0xff }])
+                .collect();
+            (pixels, ExtendedColorType::La8)
+        } else {
+            (pixels, ExtendedColorType::L8)
+        };
+        let (pixels, color_type) = match &self.logo {
+            Some(logo) => {
+                let mut rgba = match color_type {
+                    ExtendedColorType::La8 => pixels
+                        .chunks(2)
+                        .flat_map(|la| [la[0], la[0], la[0], la[1]])
+                        .collect::<Vec<_>>(),
+                    _ => pixels.iter().flat_map(|&l| [l, l, l, 0xff]).collect(),
+                };
+                logo.overlay(&mut rgba, width);
+                (rgba, ExtendedColorType::Rgba8)
+            }
+            None => (pixels, color_type),
+        };
+        PngEncoder::new(out)
+            .write_image(&pixels, width as u32, height as u32, color_type)
+            .map_err(io::Error::other)
+    }
+}
+
+/// Renders an image to grayscale pixels with each dot drawn as a square of
+/// `scale` by `scale` pixels, returning the pixels along with the width and
+/// height of the image in pixels.
+fn luma(image: &Image, scale: u32) -> (Vec<u8>, usize, usize) {
+    let (dots, width, height) = dots(image);
+    let scale = scale.max(1) as usize;
+    let mut pixels = Vec::with_capacity(width * height * scale * scale);
+    for row in dots.chunks(width.max(1)) {
+        let line = row
+            .iter()
+            .flat_map(|dot| {
+                let luma = match dot {
+                    Dot::Black => 0x00,
+                    Dot::White => 0xff,
+                };
+                std::iter::repeat_n(luma, scale)
+            })
+            .collect::<Vec<u8>>();
+        for _ in 0..scale {
+            pixels.extend_from_slice(&line);
+        }
+    }
+    (pixels, width * scale, height * scale)
+}
+
+/// Encodes an image as a PNG in memory, for embedding in terminal graphics
+/// protocols.
+fn png(image: &Image, scale: u32) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    Png {
+        scale,
+        transparent: false,
+        logo: None,
+    }
+    .write(image, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Writes images inline with the Kitty terminal graphics protocol.
+#[derive(Clone, Copy, Debug)]
+pub struct Kitty {
+    /// The size of each dot in pixels.
+    pub scale: u32,
+}
+
+impl Backend for Kitty {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        // Payloads are transmitted in chunks of at most 4096 bytes of base64,
+        // with `m=1` marking that more chunks follow.
+        const CHUNK_SIZE: usize = 4096;
+
+        let payload = BASE64.encode(png(image, self.scale)?);
+        let chunks = payload.as_bytes().chunks(CHUNK_SIZE).collect::<Vec<_>>();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = (i + 1 < chunks.len()) as u8;
+            if i == 0 {
+                write!(out, "\x1b_Ga=T,f=100,q=2,m={};", more)?;
+            } else {
+                write!(out, "\x1b_Gm={};", more)?;
+            }
+            out.write_all(chunk)?;
+            out.write_all(b"\x1b\\")?;
+        }
+        writeln!(out)
+    }
+}
+
+/// Writes images inline with the iTerm2 inline images protocol.
+#[derive(Clone, Copy, Debug)]
+pub struct Iterm2 {
+    /// The size of each dot in pixels.
+    pub scale: u32,
+}
+
+impl Backend for Iterm2 {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let png = png(image, self.scale)?;
+        writeln!(
+            out,
+            "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+            png.len(),
+            BASE64.encode(&png),
+        )
+    }
+}
+
+/// Writes images inline as sixel graphics.
+#[derive(Clone, Copy, Debug)]
+pub struct Sixel {
+    /// The size of each dot in pixels.
+    pub scale: u32,
+}
+
+impl Backend for Sixel {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let (pixels, width, height) = luma(image, self.scale);
+
+        // Start the sixel sequence with square pixels and define colour 0 as
+        // white and colour 1 as black.
+        write!(out, "\x1bP0;1q\"1;1;{};{}", width, height)?;
+        write!(out, "#0;2;100;100;100#1;2;0;0;0")?;
+        for band in (0..height).step_by(6) {
+            for (color, luma) in [(0, 0xff), (1, 0x00)] {
+                write!(out, "#{}", color)?;
+                let sixels = (0..width).map(|x| {
+                    (0..6)
+                        .filter(|dy| band + dy < height && pixels[(band + dy) * width + x] == luma)
+                        .fold(0, |bits, dy| bits | 1 << dy)
+                });
+                write_sixel_run(out, sixels)?;
+                // Return to the start of the band to draw the next colour.
+                out.write_all(b"$")?;
+            }
+            out.write_all(b"-")?;
+        }
+        out.write_all(b"\x1b\\")?;
+        writeln!(out)
+    }
+}
+
+/// Writes a row of sixels, compressing repeated sixels with run-length
+/// encoding.
+fn write_sixel_run(out: &mut dyn Write, sixels: impl Iterator<Item = u8>) -> io::Result<()> {
+    let mut write_run = |sixel: u8, count: usize| -> io::Result<()> {
+        let c = (b'?' + sixel) as char;
+        match count {
+            0 => Ok(()),
+            1..=3 => write!(out, "{}", c.to_string().repeat(count)),
+            _ => write!(out, "!{}{}", count, c),
+        }
+    };
+
+    let mut run = (0, 0);
+    for sixel in sixels {
+        if sixel == run.0 {
+            run.1 += 1;
+        } else {
+            write_run(run.0, run.1)?;
+            run = (sixel, 1);
+        }
+    }
+    write_run(run.0, run.1)
+}
+
+/// Writes images as plain PBM bitmaps, with one pixel per dot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pbm;
+
+impl Backend for Pbm {
+    fn write(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let (dots, width, height) = dots(image);
+        writeln!(out, "P1\n{} {}", width, height)?;
+        for row in dots.chunks(width.max(1)) {
+            let line = row
+                .iter()
+                .map(|dot| match dot {
+                    Dot::Black => "1",
+                    Dot::White => "0",
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Dot::{Black as B, White as W};
+
+    /// Writes the image with the backend into a buffer.
+    fn write(backend: &dyn Backend, image: &Image) -> Vec<u8> {
+        let mut out = Vec::new();
+        backend.write(image, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn svg_draws_black_dots() {
+        let image = Image::from_dots(&[B, W, W, B], 2);
+        let svg = String::from_utf8(write(
+            &Svg {
+                scale: 3,
+                transparent: false,
+                finder_style: FinderStyle::Solid,
+                logo: None,
+            },
+            &image,
+        ))
+        .unwrap();
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="6" height="6" viewBox="0 0 2 2""#
+        ));
+        assert!(svg.contains(r#"d="M0 0h1v1h-1zM1 1h1v1h-1z""#));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn png_scales_dots() {
+        let image = Image::from_dots(&[B, W, W, B, B, B], 3);
+        let png = write(
+            &Png {
+                scale: 2,
+                transparent: false,
+                logo: None,
+            },
+            &image,
+        );
+        let decoded = ::image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(decoded.dimensions(), (6, 4));
+        assert_eq!(
+            decoded.into_raw(),
+            [
+                0x00, 0x00, 0xff, 0xff, 0xff, 0xff, //
+                0x00, 0x00, 0xff, 0xff, 0xff, 0xff, //
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+            ]
+        );
+    }
+
+    #[test]
+    fn transparent_png_alpha() {
+        let image = Image::from_dots(&[B, W, W, B], 2);
+        let png = write(
+            &Png {
+                scale: 1,
+                transparent: true,
+                logo: None,
+            },
+            &image,
+        );
+        let decoded = ::image::load_from_memory(&png).unwrap();
+        assert!(decoded.color().has_alpha());
+        assert_eq!(
+            decoded.to_luma_alpha8().into_raw(),
+            [0x00, 0xff, 0xff, 0x00, 0xff, 0x00, 0x00, 0xff],
+        );
+
+        let opaque = write(
+            &Png {
+                scale: 1,
+                transparent: false,
+                logo: None,
+            },
+            &image,
+        );
+        assert!(!::image::load_from_memory(&opaque)
+            .unwrap()
+            .color()
+            .has_alpha());
+    }
+
+    #[test]
+    fn svg_finder_styles() {
+        let code = qrcode::QrCode::new(b"hello").unwrap();
+        let image = code.render::<Dot>().quiet_zone(true).build();
+        let svg_with = |image: &Image, finder_style, transparent| {
+            String::from_utf8(write(
+                &Svg {
+                    scale: 1,
+                    transparent,
+                    finder_style,
+                    logo: None,
+                },
+                image,
+            ))
+            .unwrap()
+        };
+        let svg = |image: &Image, finder_style| svg_with(image, finder_style, false);
+        let rects = |svg: &str| svg.matches("<rect").count();
+
+        // Both styles are drawn over the background rectangle.
+        assert_eq!(rects(&svg(&image, FinderStyle::Solid)), 10);
+        assert_eq!(rects(&svg(&image, FinderStyle::Outline)), 7);
+        let outline = svg(&image, FinderStyle::Outline);
+        assert!(outline.contains(
+            r##"<rect x="4.5" y="4.5" width="6" height="6" fill="none" stroke="#000" stroke-width="1"/>"##
+        ));
+        assert!(outline.contains(r##"<rect x="6" y="6" width="3" height="3" fill="#000"/>"##));
+
+        // Finders are found in scaled and rotated images too.
+        let scaled = image.scale(2).rotate(1);
+        let solid = svg(&scaled, FinderStyle::Solid);
+        assert_eq!(rects(&solid), 10);
+        assert!(solid.contains(r##"<rect x="8" y="8" width="14" height="14" fill="#000"/>"##));
+
+        // Solid finders on a transparent background leave their light ring
+        // transparent too.
+        let transparent = svg_with(&image, FinderStyle::Solid, true);
+        assert!(!transparent.contains("#fff"));
+        assert_eq!(rects(&transparent), 3);
+        assert!(transparent
+            .contains(r##"<path fill="#000" fill-rule="evenodd" d="M4 4h7v7h-7zM5 5h5v5h-5z"/>"##));
+
+        // Finder dots are not drawn as part of the path.
+        let (dots, width, height) = dots(&image);
+        let black = dots.iter().filter(|&&dot| dot == Dot::Black).count();
+        let path_dots = svg(&image, FinderStyle::Solid).matches('M').count();
+        assert_eq!(finders(&dots, width, height).0.len(), 3);
+        assert_eq!(path_dots, black - 3 * 33);
+    }
+
+    #[test]
+    fn transparent_svg_has_no_background() {
+        let image = Image::from_dots(&[B, W, W, B], 2);
+        let svg = |transparent| {
+            String::from_utf8(write(
+                &Svg {
+                    scale: 1,
+                    transparent,
+                    finder_style: FinderStyle::Solid,
+                    logo: None,
+                },
+                &image,
+            ))
+            .unwrap()
+        };
+        assert!(svg(false).contains("<rect"));
+        assert!(!svg(true).contains("<rect"));
+    }
+
+    #[test]
+    fn logo_overlay() {
+        let mut encoded = Vec::new();
+        PngEncoder::new(&mut encoded)
+            .write_image(
+                &[255, 0, 0, 255, 0, 0, 255, 128],
+                2,
+                1,
+                ExtendedColorType::Rgba8,
+            )
+            .unwrap();
+        let logo = Logo::load(&encoded).unwrap();
+
+        let image = Image::from_dots(&[B; 16], 4);
+        assert_eq!(logo.covered_dots(&image, 1), (1, 1, 2, 1));
+        assert_eq!(logo.covered_dots(&image, 3), (1, 1, 2, 1));
+        let cleared = logo.knock_out(&image, 1);
+        assert_eq!(cleared.to_dots()[4..8], [B, W, W, B]);
+
+        let png = write(
+            &Png {
+                scale: 1,
+                transparent: false,
+                logo: Some(logo.clone()),
+            },
+            &cleared,
+        );
+        let decoded = ::image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(1, 1).0, [255, 0, 0, 255]);
+        assert_eq!(decoded.get_pixel(2, 1).0, [127, 127, 255, 255]);
+        assert_eq!(decoded.get_pixel(0, 0).0, [0, 0, 0, 255]);
+
+        let svg = String::from_utf8(write(
+            &Svg {
+                scale: 2,
+                transparent: false,
+                finder_style: FinderStyle::Solid,
+                logo: Some(logo),
+            },
+            &cleared,
+        ))
+        .unwrap();
+        assert!(svg.contains(
+            r#"<image x="1.5" y="1.5" width="1" height="0.5" href="data:image/png;base64,"#
+        ));
+    }
+
+    #[test]
+    fn pbm_bitmap() {
+        let image = Image::from_dots(&[B, W, W, W, B, B], 3);
+        let pbm = String::from_utf8(write(&Pbm, &image)).unwrap();
+        assert_eq!(pbm, "P1\n3 2\n1 0 0\n0 1 1\n");
+    }
+
+    #[test]
+    fn sixel_bands_and_runs() {
+        let image = Image::from_dots(&[B, W], 2);
+        let sixel = String::from_utf8(write(&Sixel { scale: 1 }, &image)).unwrap();
+        assert_eq!(
+            sixel,
+            "\x1bP0;1q\"1;1;2;1#0;2;100;100;100#1;2;0;0;0#0?@$#1@?$-\x1b\\\n",
+        );
+
+        let image = Image::from_dots(&[B; 5], 5);
+        let sixel = String::from_utf8(write(&Sixel { scale: 1 }, &image)).unwrap();
+        assert!(sixel.contains("#0!5?$#1!5@$-"));
+    }
+
+    #[test]
+    fn kitty_chunks_png_payload() {
+        let image = Image::from_dots(&[B, W, W, B], 2);
+        let kitty = String::from_utf8(write(&Kitty { scale: 1 }, &image)).unwrap();
+        let payload = kitty
+            .strip_prefix("\x1b_Ga=T,f=100,q=2,m=0;")
+            .and_then(|rest| rest.strip_suffix("\x1b\\\n"))
+            .unwrap();
+        assert_eq!(
+            BASE64.decode(payload).unwrap(),
+            write(
+                &Png {
+                    scale: 1,
+                    transparent: false,
+                    logo: None,
+                },
+                &image
+            )
+        );
+
+        // A noisy image does not compress well, so its payload spans chunks.
+        let dots = (0..10_000)
+            .map(|i: u32| {
+                if i.wrapping_mul(2_654_435_761) >> 31 == 0 {
+                    B
+                } else {
+                    W
+                }
+            })
+            .collect::<Vec<_>>();
+        let image = Image::from_dots(&dots, 100);
+        let kitty = String::from_utf8(write(&Kitty { scale: 4 }, &image)).unwrap();
+        let chunks = kitty
+            .trim_end()
+            .split_terminator("\x1b\\")
+            .collect::<Vec<_>>();
+        assert!(chunks.len() > 1);
+        assert!(chunks[0].starts_with("\x1b_Ga=T,f=100,q=2,m=1;"));
+        for chunk in &chunks[1..chunks.len() - 1] {
+            assert!(chunk.starts_with("\x1b_Gm=1;"));
+        }
+        assert!(chunks[chunks.len() - 1].starts_with("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn iterm2_inline_file() {
+        let image = Image::from_dots(&[B, W, W, B], 2);
+        let png = write(
+            &Png {
+                scale: 2,
+                transparent: false,
+                logo: None,
+            },
+            &image,
+        );
+        let iterm2 = String::from_utf8(write(&Iterm2 { scale: 2 }, &image)).unwrap();
+        assert_eq!(
+            iterm2,
+            format!(
+                "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07\n",
+                png.len(),
+                BASE64.encode(&png),
+            ),
+        );
+    }
+}
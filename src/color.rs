@@ -0,0 +1,310 @@
+//! Colour support for rendering QR codes with ANSI escape sequences.
+//!
+//! Like the [`crate::core`] module, this only depends on `core` and `alloc`.
+
+#![deny(clippy::std_instead_of_alloc, clippy::std_instead_of_core)]
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+
+/// The escape sequence for resetting all terminal colours and attributes.
+pub const RESET: &str = "\x1b[0m";
+
+/// The colour depth supported by a terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorDepth {
+    /// The 16 standard and bright ANSI colours.
+    Ansi16,
+    /// The 256 colour palette.
+    Ansi256,
+    /// 24-bit truecolor.
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Detects the colour depth from the values of the `TERM` and `COLORTERM`
+    /// environment variables, falling back to 16 colours.
+    pub fn detect(term: Option<&str>, colorterm: Option<&str>) -> Self {
+        match (term, colorterm) {
+            (_, Some("truecolor" | "24bit")) => ColorDepth::TrueColor,
+            (Some(term), _) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+/// A 24-bit RGB colour.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Pure black.
+    pub const BLACK: Rgb = Rgb(0, 0, 0);
+    /// Pure white.
+    pub const WHITE: Rgb = Rgb(255, 255, 255);
+
+    /// Returns the escape sequence for setting this colour as the foreground.
+    pub fn fg(self) -> String {
+        format!("\x1b[38;2;{};{};{}m", self.0, self.1, self.2)
+    }
+
+    /// Returns the escape sequence for setting this colour as the background.
+    pub fn bg(self) -> String {
+        format!("\x1b[48;2;{};{};{}m", self.0, self.1, self.2)
+    }
+
+    /// Returns the escape sequence for setting this colour, or the nearest one
+    /// available with the specified colour depth, as the foreground.
+    pub fn fg_for(self, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::Ansi16 => match self.to_ansi16() {
+                i @ 0..=7 => format!("\x1b[{}m", 30 + i),
+                i => format!("\x1b[{}m", 90 + i - 8),
+            },
+            ColorDepth::Ansi256 => format!("\x1b[38;5;{}m", self.to_ansi256()),
+            ColorDepth::TrueColor => self.fg(),
+        }
+    }
+
+    /// Returns the escape sequence for setting this colour, or the nearest one
+    /// available with the specified colour depth, as the background.
+    pub fn bg_for(self, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::Ansi16 => match self.to_ansi16() {
+                i @ 0..=7 => format!("\x1b[{}m", 40 + i),
+                i => format!("\x1b[{}m", 100 + i - 8),
+            },
+            ColorDepth::Ansi256 => format!("\x1b[48;5;{}m", self.to_ansi256()),
+            ColorDepth::TrueColor => self.bg(),
+        }
+    }
+
+    /// Returns the index of the nearest of the 16 standard and bright ANSI
+    /// colours, using the default xterm values for the palette.
+    pub fn to_ansi16(self) -> u8 {
+        const PALETTE: [Rgb; 16] = [
+            Rgb(0, 0, 0),
+            Rgb(205, 0, 0),
+            Rgb(0, 205, 0),
+            Rgb(205, 205, 0),
+            Rgb(0, 0, 238),
+            Rgb(205, 0, 205),
+            Rgb(0, 205, 205),
+            Rgb(229, 229, 229),
+            Rgb(127, 127, 127),
+            Rgb(255, 0, 0),
+            Rgb(0, 255, 0),
+            Rgb(255, 255, 0),
+            Rgb(92, 92, 255),
+            Rgb(255, 0, 255),
+            Rgb(0, 255, 255),
+            Rgb(255, 255, 255),
+        ];
+
+        (0..16)
+            .min_by_key(|&i| self.distance(PALETTE[i]))
+            .unwrap_or(0) as u8
+    }
+
+    /// Returns the index of the nearest colour in the standard 256 colour
+    /// palette, considering only the 6x6x6 colour cube and the grayscale ramp
+    /// since the first 16 colours vary between terminal themes.
+    pub fn to_ansi256(self) -> u8 {
+        const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+        let nearest_level = |c: u8| {
+            (0..6)
+                .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+                .unwrap_or(0)
+        };
+
+        let (r, g, b) = (
+            nearest_level(self.0),
+            nearest_level(self.1),
+            nearest_level(self.2),
+        );
+        let cube = Rgb(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+        let cube_index = (16 + 36 * r + 6 * g + b) as u8;
+
+        let average = (self.0 as u32 + self.1 as u32 + self.2 as u32) / 3;
+        let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+        let gray_level = 8 + 10 * gray_step;
+        let gray = Rgb(gray_level, gray_level, gray_level);
+        let gray_index = 232 + gray_step;
+
+        if self.distance(gray) < self.distance(cube) {
+            gray_index
+        } else {
+            cube_index
+        }
+    }
+
+    /// Returns the squared Euclidean distance between two colours.
+    fn distance(self, other: Rgb) -> i32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(self.0, other.0) + d(self.1, other.1) + d(self.2, other.2)
+    }
+}
+
+/// Named colours accepted when parsing, matching their CSS values.
+const NAMED_COLORS: &[(&str, Rgb)] = &[
+    ("black", Rgb(0, 0, 0)),
+    ("white", Rgb(255, 255, 255)),
+    ("red", Rgb(255, 0, 0)),
+    ("green", Rgb(0, 128, 0)),
+    ("lime", Rgb(0, 255, 0)),
+    ("blue", Rgb(0, 0, 255)),
+    ("yellow", Rgb(255, 255, 0)),
+    ("cyan", Rgb(0, 255, 255)),
+    ("magenta", Rgb(255, 0, 255)),
+    ("orange", Rgb(255, 165, 0)),
+    ("purple", Rgb(128, 0, 128)),
+    ("maroon", Rgb(128, 0, 0)),
+    ("navy", Rgb(0, 0, 128)),
+    ("olive", Rgb(128, 128, 0)),
+    ("teal", Rgb(0, 128, 128)),
+    ("silver", Rgb(192, 192, 192)),
+    ("gray", Rgb(128, 128, 128)),
+    ("grey", Rgb(128, 128, 128)),
+];
+
+impl FromStr for Rgb {
+    type Err = ParseColorError;
+
+    /// Parses a colour in `#RRGGBB`, `#RGB` or `rgb(r, g, b)` notation, or a
+    /// named colour such as `black` or `red`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseColorError(s.to_owned());
+        let color = s.trim().to_ascii_lowercase();
+
+        if let Some(hex) = color.strip_prefix('#') {
+            let digits = hex
+                .chars()
+                .map(|c| c.to_digit(16).map(|d| d as u8))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(err)?;
+            return match digits[..] {
+                [r, g, b] => Ok(Rgb(r * 0x11, g * 0x11, b * 0x11)),
+                [r1, r0, g1, g0, b1, b0] => Ok(Rgb(r1 << 4 | r0, g1 << 4 | g0, b1 << 4 | b0)),
+                _ => Err(err()),
+            };
+        }
+
+        if let Some(components) = color
+            .strip_prefix("rgb(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let components = components
+                .split(',')
+                .map(|component| component.trim().parse::<u8>().ok())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(err)?;
+            return match components[..] {
+                [r, g, b] => Ok(Rgb(r, g, b)),
+                _ => Err(err()),
+            };
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| *name == color)
+            .map(|(_, rgb)| *rgb)
+            .ok_or_else(err)
+    }
+}
+
+/// An error parsing a colour, containing the offending input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseColorError(pub String);
+
+impl Display for ParseColorError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "invalid colour '{}'", self.0)
+    }
+}
+
+impl Error for ParseColorError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepted_formats() {
+        for &(input, expected) in &[
+            ("#1a2B3c", Rgb(0x1a, 0x2b, 0x3c)),
+            ("#fa0", Rgb(0xff, 0xaa, 0x00)),
+            ("rgb(1, 2, 3)", Rgb(1, 2, 3)),
+            ("RGB(255,0,128)", Rgb(255, 0, 128)),
+            ("black", Rgb(0, 0, 0)),
+            ("White", Rgb(255, 255, 255)),
+            (" orange ", Rgb(255, 165, 0)),
+        ] {
+            assert_eq!(input.parse::<Rgb>(), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_rejections() {
+        for &input in &[
+            "",
+            "#",
+            "#12",
+            "#12345",
+            "#ggg",
+            "rgb(1, 2)",
+            "rgb(1, 2, 256)",
+            "rgb(1,2,3",
+            "beige",
+        ] {
+            assert_eq!(
+                input.parse::<Rgb>(),
+                Err(ParseColorError(input.to_owned())),
+                "{}",
+                input,
+            );
+        }
+    }
+
+    #[test]
+    fn detect_color_depth() {
+        for &(term, colorterm, expected) in &[
+            (
+                Some("xterm-256color"),
+                Some("truecolor"),
+                ColorDepth::TrueColor,
+            ),
+            (None, Some("24bit"), ColorDepth::TrueColor),
+            (Some("xterm-256color"), None, ColorDepth::Ansi256),
+            (Some("screen-256color"), Some(""), ColorDepth::Ansi256),
+            (Some("xterm"), None, ColorDepth::Ansi16),
+            (None, None, ColorDepth::Ansi16),
+        ] {
+            assert_eq!(ColorDepth::detect(term, colorterm), expected);
+        }
+    }
+
+    #[test]
+    fn escapes_for_color_depth() {
+        let red = Rgb(255, 0, 0);
+        assert_eq!(red.fg_for(ColorDepth::Ansi16), "\x1b[91m");
+        assert_eq!(red.bg_for(ColorDepth::Ansi16), "\x1b[101m");
+        assert_eq!(Rgb::BLACK.fg_for(ColorDepth::Ansi16), "\x1b[30m");
+        assert_eq!(Rgb::BLACK.bg_for(ColorDepth::Ansi16), "\x1b[40m");
+
+        assert_eq!(red.fg_for(ColorDepth::Ansi256), "\x1b[38;5;196m");
+        assert_eq!(Rgb::WHITE.bg_for(ColorDepth::Ansi256), "\x1b[48;5;231m");
+        assert_eq!(
+            Rgb(128, 128, 128).fg_for(ColorDepth::Ansi256),
+            "\x1b[38;5;244m"
+        );
+
+        assert_eq!(red.fg_for(ColorDepth::TrueColor), "\x1b[38;2;255;0;0m");
+        assert_eq!(red.bg_for(ColorDepth::TrueColor), "\x1b[48;2;255;0;0m");
+    }
+}
@@ -0,0 +1,85 @@
+//! Payload compression for squeezing more data into a single QR code. Note
+//! that compressed payloads are prefixed with a magic value so that a decoder
+//! can distinguish them from regular data and knows to decompress them.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Read, Write};
+
+/// The magic prefix that marks a compressed payload. It is followed by a gzip
+/// stream of the original data.
+pub const MAGIC: &[u8] = b"QRZ:";
+
+/// Compresses data with gzip, prepending the compressed payload magic.
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(MAGIC.to_vec(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// The magic bytes that start every gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Returns true if the payload looks like one produced by [`compress`], that
+/// is it starts with the compressed payload magic followed by a gzip header.
+///
+/// Checking for the gzip header as well means that plain text payloads which
+/// happen to start with the magic are not mistaken for compressed ones.
+pub fn is_compressed(payload: &[u8]) -> bool {
+    payload
+        .strip_prefix(MAGIC)
+        .is_some_and(|stream| stream.starts_with(GZIP_MAGIC))
+}
+
+/// Decompresses a payload produced by [`compress`], returning an error if it
+/// does not start with the compressed payload magic.
+pub fn decompress(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let stream = payload.strip_prefix(MAGIC).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing compressed payload magic",
+        )
+    })?;
+    let mut data = Vec::new();
+    GzDecoder::new(stream).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode;
+    use qrcode::EcLevel;
+
+    #[test]
+    fn round_trip() {
+        let data = b"hello hello hello hello";
+        let payload = compress(data).unwrap();
+        assert!(payload.starts_with(MAGIC));
+        assert_eq!(decompress(&payload).unwrap(), data);
+    }
+
+    #[test]
+    fn detects_compressed_payloads() {
+        assert!(is_compressed(&compress(b"hello").unwrap()));
+        assert!(!is_compressed(b"hello"));
+        assert!(!is_compressed(b"QRZ:plain text"));
+        assert!(!is_compressed(MAGIC));
+    }
+
+    #[test]
+    fn decompress_requires_magic() {
+        let payload = compress(b"hello").unwrap();
+        assert!(decompress(&payload[MAGIC.len()..]).is_err());
+    }
+
+    #[test]
+    fn compressible_data_fits_in_smaller_version() {
+        let data = "all work and no play makes jack a dull boy\n".repeat(20);
+        let (uncompressed, _) = encode::plan(data.as_bytes(), EcLevel::M).unwrap();
+        let (compressed, _) =
+            encode::plan(&compress(data.as_bytes()).unwrap(), EcLevel::M).unwrap();
+        assert!(compressed.width() < uncompressed.width());
+    }
+}
@@ -0,0 +1,311 @@
+//! Platform specific console setup required for rendering QR codes.
+
+use crate::color::Rgb;
+use std::io;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Sets up the console for rendering QR codes.
+///
+/// On Windows, this sets the console output codepage to UTF-8 so that the
+/// unicode block characters are displayed correctly, and enables virtual
+/// terminal processing for ANSI escape sequences. Nothing is done when the
+/// standard output is not a console. On other platforms, this is a no-op.
+#[cfg(windows)]
+pub fn setup() -> io::Result<()> {
+    use winapi::um::consoleapi::{GetConsoleMode, SetConsoleMode};
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::processenv::GetStdHandle;
+    use winapi::um::winbase::STD_OUTPUT_HANDLE;
+    use winapi::um::wincon::{SetConsoleOutputCP, ENABLE_VIRTUAL_TERMINAL_PROCESSING};
+    use winapi::um::winnls::CP_UTF8;
+
+    // SAFETY: The handle is checked before use and the mode pointer points to
+    // a valid local variable.
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+            return Ok(());
+        }
+        let mut mode = 0;
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            return Ok(());
+        }
+
+        if SetConsoleOutputCP(CP_UTF8) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets up the console for rendering QR codes.
+///
+/// This is only required on Windows and is a no-op on other platforms.
+#[cfg(not(windows))]
+pub fn setup() -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns the width of the terminal attached to standard output in columns,
+/// or `None` if standard output is not a terminal.
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    use std::mem::MaybeUninit;
+
+    // SAFETY: The window size structure is initialized by `ioctl` before use.
+    let size = unsafe {
+        let mut size = MaybeUninit::<libc::winsize>::uninit();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+            return None;
+        }
+        size.assume_init()
+    };
+    Some(size.ws_col as usize).filter(|&columns| columns > 0)
+}
+
+/// Returns the width of the console attached to standard output in columns,
+/// or `None` if standard output is not a console.
+#[cfg(windows)]
+pub fn terminal_width() -> Option<usize> {
+    use std::mem::MaybeUninit;
+    use winapi::um::processenv::GetStdHandle;
+    use winapi::um::winbase::STD_OUTPUT_HANDLE;
+    use winapi::um::wincon::GetConsoleScreenBufferInfo;
+
+    // SAFETY: The handle is checked by `GetConsoleScreenBufferInfo` and the
+    // buffer info structure is initialized by it before use.
+    let info = unsafe {
+        let mut info = MaybeUninit::uninit();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), info.as_mut_ptr()) == 0 {
+            return None;
+        }
+        info.assume_init()
+    };
+    let columns = info.srWindow.Right - info.srWindow.Left + 1;
+    Some(columns as usize).filter(|&columns| columns > 0)
+}
+
+/// Returns the width of the terminal in columns.
+///
+/// Detecting the terminal width is not supported on this platform, so this
+/// always returns `None`.
+#[cfg(not(any(unix, windows)))]
+pub fn terminal_width() -> Option<usize> {
+    None
+}
+
+/// Whether the terminal was resized since the last call to [`take_resize`].
+#[cfg(unix)]
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a `SIGWINCH` handler that records terminal resizes, so that they
+/// can be polled with [`take_resize`].
+#[cfg(unix)]
+pub fn watch_resize() -> io::Result<()> {
+    extern "C" fn on_resize(_: libc::c_int) {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: The handler only stores to an atomic, which is async-signal-safe.
+    let handler = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGWINCH, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Installs a handler that records terminal resizes.
+///
+/// Resizes are only detected on Unix platforms, so this is a no-op on other
+/// platforms.
+#[cfg(not(unix))]
+pub fn watch_resize() -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns whether the terminal was resized since the last call, as recorded
+/// by the handler installed with [`watch_resize`].
+#[cfg(unix)]
+pub fn take_resize() -> bool {
+    RESIZED.swap(false, Ordering::Relaxed)
+}
+
+/// Returns whether the terminal was resized since the last call.
+///
+/// Resizes are only detected on Unix platforms, so this always returns
+/// `false` on other platforms.
+#[cfg(not(unix))]
+pub fn take_resize() -> bool {
+    false
+}
+
+/// Queries the terminal's background colour with an OSC 11 escape sequence,
+/// returning `None` if there is no controlling terminal or it does not reply
+/// within a short timeout.
+#[cfg(unix)]
+pub fn query_background() -> io::Result<Option<Rgb>> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::mem::MaybeUninit;
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(_) => return Ok(None),
+    };
+    let fd = tty.as_raw_fd();
+
+    // SAFETY: The file descriptor is valid for the lifetime of `tty` and the
+    // `termios` structure is initialized by `tcgetattr` before use.
+    let original = unsafe {
+        let mut termios = MaybeUninit::uninit();
+        if libc::tcgetattr(fd, termios.as_mut_ptr()) != 0 {
+            return Ok(None);
+        }
+        termios.assume_init()
+    };
+
+    // Switch to non-canonical mode without echo so that the reply can be read
+    // as it arrives, with reads timing out after a tenth of a second.
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 1;
+    // SAFETY: See above.
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let result = (|| {
+        tty.write_all(b"\x1b]11;?\x1b\\")?;
+        tty.flush()?;
+
+        let mut response = Vec::new();
+        let mut buffer = [0; 64];
+        loop {
+            let len = tty.read(&mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            response.extend_from_slice(&buffer[..len]);
+            if response.ends_with(b"\x07") || response.ends_with(b"\x1b\\") {
+                break;
+            }
+        }
+        Ok(parse_background_response(&response))
+    })();
+
+    // SAFETY: See above.
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    result
+}
+
+/// Queries the terminal's background colour.
+///
+/// This is only supported on Unix platforms and always returns `None` on
+/// other platforms.
+#[cfg(not(unix))]
+pub fn query_background() -> io::Result<Option<Rgb>> {
+    Ok(None)
+}
+
+/// Parses a terminal's reply to an OSC 11 background colour query, such as
+/// `ESC ] 11 ; rgb:ffff/ffff/ffff ESC \`, terminated by either a string
+/// terminator or a BEL character.
+///
+/// Each component can have from 1 to 4 hexadecimal digits and is scaled to 8
+/// bits.
+pub fn parse_background_response(response: &[u8]) -> Option<Rgb> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("\x1b]11;")?;
+    let reply = &response[start + 5..];
+    let reply = reply
+        .strip_suffix('\x07')
+        .or_else(|| reply.strip_suffix("\x1b\\"))?;
+    let components = reply
+        .strip_prefix("rgb:")?
+        .split('/')
+        .map(|component| {
+            if component.is_empty() || component.len() > 4 {
+                return None;
+            }
+            let value = u32::from_str_radix(component, 16).ok()?;
+            let max = (1 << (4 * component.len())) - 1;
+            Some((value * 255 / max) as u8)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match components[..] {
+        [r, g, b] => Some(Rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_succeeds() {
+        setup().unwrap();
+    }
+
+    #[test]
+    fn parse_background_responses() {
+        let cases: &[(&[u8], Option<Rgb>)] = &[
+            (b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\", Some(Rgb::WHITE)),
+            (b"\x1b]11;rgb:0000/0000/0000\x07", Some(Rgb::BLACK)),
+            (
+                b"\x1b]11;rgb:1e1e/2020/2828\x07",
+                Some(Rgb(0x1e, 0x20, 0x28)),
+            ),
+            (b"\x1b]11;rgb:f/8/0\x07", Some(Rgb(255, 136, 0))),
+            (b"noise\x1b]11;rgb:ff/00/80\x1b\\", Some(Rgb(255, 0, 128))),
+            (b"\x1b]11;rgb:ffff/ffff/ffff", None),
+            (b"\x1b]11;rgb:ffff/ffff\x07", None),
+            (b"\x1b]11;rgb:fffff/0/0\x07", None),
+            (b"\x1b]11;rgb:zz/00/00\x07", None),
+            (b"\x1b]10;rgb:ffff/ffff/ffff\x07", None),
+            (b"\xff", None),
+        ];
+        for (response, expected) in cases {
+            assert_eq!(parse_background_response(response), *expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resize_signal_is_recorded() {
+        watch_resize().unwrap();
+        take_resize();
+        assert!(!take_resize());
+
+        // SAFETY: Raising a signal with an installed handler is safe.
+        assert_eq!(unsafe { libc::raise(libc::SIGWINCH) }, 0);
+        assert!(take_resize());
+        assert!(!take_resize());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn setup_switches_console_to_utf8() {
+        use winapi::um::consoleapi::{GetConsoleMode, GetConsoleOutputCP};
+        use winapi::um::processenv::GetStdHandle;
+        use winapi::um::winbase::STD_OUTPUT_HANDLE;
+        use winapi::um::winnls::CP_UTF8;
+
+        setup().unwrap();
+
+        // SAFETY: The mode pointer points to a valid local variable.
+        unsafe {
+            let mut mode = 0;
+            if GetConsoleMode(GetStdHandle(STD_OUTPUT_HANDLE), &mut mode) != 0 {
+                assert_eq!(GetConsoleOutputCP(), CP_UTF8);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Colour contrast calculations for keeping coloured QR codes scannable,
+//! using the relative luminance and contrast ratio definitions from WCAG 2.
+
+use crate::color::Rgb;
+
+/// The minimum contrast ratio between the foreground and background colours
+/// for a QR code to reliably scan.
+pub const MIN_CONTRAST: f64 = 3.0;
+
+/// Returns the relative luminance of a colour, from 0 for black to 1 for
+/// white.
+pub fn relative_luminance(color: Rgb) -> f64 {
+    let linear = |channel: u8| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.0) + 0.7152 * linear(color.1) + 0.0722 * linear(color.2)
+}
+
+/// Returns the contrast ratio between two colours, from 1 for identical
+/// colours to 21 for black and white.
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Adjusts the background colour when it does not contrast enough with the
+/// foreground colour, replacing it with black or white, whichever contrasts
+/// more. Returns the possibly adjusted `(foreground, background)` pair.
+pub fn auto_contrast(foreground: Rgb, background: Rgb) -> (Rgb, Rgb) {
+    if contrast_ratio(foreground, background) >= MIN_CONTRAST {
+        return (foreground, background);
+    }
+    let background =
+        if contrast_ratio(foreground, Rgb::WHITE) >= contrast_ratio(foreground, Rgb::BLACK) {
+            Rgb::WHITE
+        } else {
+            Rgb::BLACK
+        };
+    (foreground, background)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luminance_and_contrast() {
+        assert_eq!(relative_luminance(Rgb::BLACK), 0.0);
+        assert_eq!(relative_luminance(Rgb::WHITE), 1.0);
+        assert!((relative_luminance(Rgb(128, 128, 128)) - 0.2158).abs() < 1e-4);
+
+        assert_eq!(contrast_ratio(Rgb::BLACK, Rgb::WHITE), 21.0);
+        assert_eq!(contrast_ratio(Rgb::WHITE, Rgb::BLACK), 21.0);
+        assert_eq!(contrast_ratio(Rgb(1, 2, 3), Rgb(1, 2, 3)), 1.0);
+    }
+
+    #[test]
+    fn auto_contrast_replaces_low_contrast_background() {
+        let navy = Rgb(0, 0, 128);
+        assert_eq!(auto_contrast(navy, Rgb(20, 20, 20)), (navy, Rgb::WHITE));
+        assert_eq!(
+            auto_contrast(Rgb(255, 255, 0), Rgb(250, 250, 250)),
+            (Rgb(255, 255, 0), Rgb::BLACK)
+        );
+        assert_eq!(
+            auto_contrast(navy, Rgb(255, 255, 0)),
+            (navy, Rgb(255, 255, 0))
+        );
+    }
+}
@@ -0,0 +1,607 @@
+//! Core rendering logic for packing QR dots into terminal characters. Note
+//! that we create a "point" abstraction for representing two QR dots. This is
+//! done because terminal characters are vertical and can display two QR dots.
+//! This means that when rendering, we print out two dots at a time per
+//! character.
+//!
+//! This module only depends on `core` and `alloc` so that the packing logic can
+//! be reused in `no_std` environments.
+
+#![deny(clippy::std_instead_of_alloc, clippy::std_instead_of_core)]
+
+use crate::color::Rgb;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A QR dot that can either be white or black.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dot {
+    /// A black dot.
+    Black,
+    /// A white dot.
+    White,
+}
+
+/// A rendering character. This is slightly different than a dot as terminal
+/// characters have a height of two dots.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub top: Dot,
+    pub bot: Dot,
+}
+
+impl Point {
+    /// Converts a point to a unicode block character.
+    ///
+    /// Note this method assume `Black` to be filled in, meaning it will look
+    /// "correct" when using a white background and black font colour.
+    pub fn to_char(&self) -> char {
+        match (self.top, self.bot) {
+            (Dot::Black, Dot::Black) => '█',
+            (Dot::Black, Dot::White) => '▀',
+            (Dot::White, Dot::Black) => '▄',
+            (Dot::White, Dot::White) => ' ',
+        }
+    }
+
+    /// Converts a point to ASCII characters for terminals without unicode
+    /// support. Since a single ASCII character cannot draw two dots, each dot
+    /// is drawn as two characters on its own line, returning the top and
+    /// bottom lines respectively.
+    pub fn to_ascii(&self) -> [&'static str; 2] {
+        [ascii(self.top), ascii(self.bot)]
+    }
+
+    /// Converts a point to a unicode block character wrapped in truecolor
+    /// escape sequences, drawing `Black` dots with the `fg` colour and `White`
+    /// dots with the `bg` colour.
+    ///
+    /// Note that the colours are not reset after the glyph so that callers can
+    /// batch an entire line and emit a single [`crate::color::RESET`] at the
+    /// end of it.
+    pub fn to_ansi(&self, fg: Rgb, bg: Rgb) -> String {
+        format!("{}{}{}", fg.fg(), bg.bg(), self.to_char())
+    }
+}
+
+/// A half point, when there is an uneven number of rows. The distiction is
+/// important when using 256 colors where the true black is different than
+/// terminal background off-black.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HalfPoint(pub Dot);
+
+impl HalfPoint {
+    /// Converts a half point to a unicode block character.
+    ///
+    /// See [`Point::to_char`] for more details.
+    pub fn to_char(&self) -> char {
+        match self.0 {
+            Dot::Black => '▀',
+            Dot::White => ' ',
+        }
+    }
+
+    /// Converts a half point to ASCII characters.
+    ///
+    /// See [`Point::to_ascii`] for more details.
+    pub fn to_ascii(&self) -> &'static str {
+        ascii(self.0)
+    }
+
+    /// Converts a half point to an upper half block character wrapped in
+    /// truecolor escape sequences. The top half is drawn with either the `fg`
+    /// or `bg` colour depending on the dot, while the bottom half is left with
+    /// the terminal's default background.
+    ///
+    /// See [`Point::to_ansi`] for more details on resetting colours.
+    pub fn to_ansi(&self, fg: Rgb, bg: Rgb) -> String {
+        let color = match self.0 {
+            Dot::Black => fg,
+            Dot::White => bg,
+        };
+        format!("{}\x1b[49m▀", color.fg())
+    }
+}
+
+/// Converts a dot to a pair of ASCII characters, drawing `Black` dots as `##`
+/// and `White` dots as spaces. Two characters are used so that the dot is
+/// roughly square.
+fn ascii(dot: Dot) -> &'static str {
+    match dot {
+        Dot::Black => "##",
+        Dot::White => "  ",
+    }
+}
+
+/// The set of characters used for rendering.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Charset {
+    /// Unicode block characters.
+    Unicode,
+    /// ASCII characters, for terminals and consoles without unicode support.
+    Ascii,
+}
+
+/// The number of dots packed into each terminal character.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Density {
+    /// One column and two rows of dots per character, using half block
+    /// characters. This is the default, as it keeps modules square.
+    Half,
+    /// Two columns and two rows of dots per character, using quadrant block
+    /// characters.
+    Quad,
+    /// Two columns and four rows of dots per character, using braille
+    /// patterns.
+    Braille,
+}
+
+impl Density {
+    /// Returns the number of `(columns, rows)` of dots per character.
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Density::Half => (1, 2),
+            Density::Quad => (2, 2),
+            Density::Braille => (2, 4),
+        }
+    }
+
+    /// Converts a cell of dots to a character, where `filled` returns whether
+    /// the dot at the `(column, row)` within the cell is `Black`.
+    pub fn to_char(self, filled: impl Fn(usize, usize) -> bool) -> char {
+        // Quadrant characters indexed by their filled quadrants, with bits
+        // for the top-left, top-right, bottom-left and bottom-right quadrants
+        // from least to most significant.
+        const QUADRANTS: [char; 16] = [
+            ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+        ];
+        // The bit of each braille dot, indexed by row then column.
+        const BRAILLE_DOTS: [[u32; 2]; 4] =
+            [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        match self {
+            Density::Half => Point {
+                top: if filled(0, 0) { Dot::Black } else { Dot::White },
+                bot: if filled(0, 1) { Dot::Black } else { Dot::White },
+            }
+            .to_char(),
+            Density::Quad => {
+                let index = (0..4)
+                    .filter(|&i| filled(i % 2, i / 2))
+                    .fold(0, |index, i| index | 1 << i);
+                QUADRANTS[index]
+            }
+            Density::Braille => {
+                let bits = (0..8)
+                    .filter(|&i| filled(i % 2, i / 2))
+                    .fold(0, |bits, i| bits | BRAILLE_DOTS[i / 2][i % 2]);
+                char::from_u32(0x2800 + bits).unwrap_or(' ')
+            }
+        }
+    }
+}
+
+/// A QR image for rendering to the terminal.
+///
+/// Points are stored in a single row-major buffer for cache locality when
+/// rendering large codes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Image {
+    points: Vec<Point>,
+    width: usize,
+    last_line: Option<Vec<HalfPoint>>,
+}
+
+impl Image {
+    /// Packs a row-major buffer of dots with the specified width into an image,
+    /// two rows of dots per line.
+    pub fn from_dots(dots: &[Dot], width: usize) -> Self {
+        if width == 0 {
+            return Image {
+                points: Vec::new(),
+                width,
+                last_line: None,
+            };
+        }
+
+        let w = width;
+        let mut points = Vec::with_capacity(dots.len() / 2);
+        let mut last_line = None;
+
+        for line in dots.chunks(w * 2) {
+            if line.len() == w * 2 {
+                points.extend(
+                    line[..w]
+                        .iter()
+                        .zip(&line[w..])
+                        .map(|(&top, &bot)| Point { top, bot }),
+                );
+            } else {
+                last_line = Some(line.iter().copied().map(HalfPoint).collect())
+            }
+        }
+
+        Image {
+            points,
+            width,
+            last_line,
+        }
+    }
+
+    /// Creates a checkerboard test pattern of the specified dimensions in dots,
+    /// alternating between `dark` and `light` starting with a `dark` dot in the
+    /// top-left corner.
+    pub fn checkerboard(width: usize, height: usize, dark: Dot, light: Dot) -> Self {
+        let dots = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| if (x + y) % 2 == 0 { dark } else { light })
+            .collect::<Vec<_>>();
+        Image::from_dots(&dots, width)
+    }
+
+    /// Unpacks the image into a row-major buffer of dots. The width of the
+    /// buffer is the number of columns of the image.
+    pub fn to_dots(&self) -> Vec<Dot> {
+        let mut dots = Vec::new();
+        for line in self.rows() {
+            dots.extend(line.iter().map(|point| point.top));
+            dots.extend(line.iter().map(|point| point.bot));
+        }
+        if let Some(last_line) = &self.last_line {
+            dots.extend(last_line.iter().map(|point| point.0));
+        }
+        dots
+    }
+
+    /// Returns the number of full lines of points in the image, excluding the
+    /// last half line.
+    pub fn line_count(&self) -> usize {
+        self.points.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    /// Returns the `i`-th line of points.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than [`Image::line_count`].
+    pub fn row(&self, i: usize) -> &[Point] {
+        &self.points[i * self.width..][..self.width]
+    }
+
+    /// Returns an iterator over the full lines of points in the image.
+    pub fn rows(&self) -> impl Iterator<Item = &[Point]> {
+        self.points.chunks(self.width.max(1))
+    }
+
+    /// Returns the last half line, when the image has an uneven number of
+    /// rows of dots.
+    pub fn last_line(&self) -> Option<&[HalfPoint]> {
+        self.last_line.as_deref()
+    }
+
+    /// Returns the dimensions of the image in characters as a `(columns, rows)`
+    /// tuple, including the last half line if there is one.
+    pub fn dimensions(&self) -> (usize, usize) {
+        let rows = self.line_count() + self.last_line.iter().count();
+        let columns = if rows > 0 { self.width } else { 0 };
+        (columns, rows)
+    }
+
+    /// Calls `f` with the row, column and point for every full line point of
+    /// the image, in row-major order.
+    pub fn visit<F: FnMut(usize, usize, &Point)>(&self, mut f: F) {
+        for (row, line) in self.rows().enumerate() {
+            for (column, point) in line.iter().enumerate() {
+                f(row, column, point);
+            }
+        }
+    }
+
+    /// Calls `f` with the column and half point for every point of the last
+    /// half line, if there is one. The row of the half line is
+    /// [`Image::line_count`].
+    pub fn visit_half_line<F: FnMut(usize, &HalfPoint)>(&self, mut f: F) {
+        for (column, point) in self.last_line().into_iter().flatten().enumerate() {
+            f(column, point);
+        }
+    }
+
+    /// Rotates the image clockwise by the specified number of quarter turns,
+    /// repacking the rotated dots into points.
+    pub fn rotate(&self, quarter_turns: usize) -> Image {
+        let (width, dots) = (self.dimensions().0, self.to_dots());
+        let height = dots.len().checked_div(width).unwrap_or(0);
+        let dot = |x: usize, y: usize| dots[x + y * width];
+
+        match quarter_turns % 4 {
+            1 => {
+                let rotated = (0..width)
+                    .flat_map(|y| (0..height).map(move |x| (x, y)))
+                    .map(|(x, y)| dot(y, height - 1 - x))
+                    .collect::<Vec<_>>();
+                Image::from_dots(&rotated, height)
+            }
+            2 => {
+                let rotated = dots.iter().rev().copied().collect::<Vec<_>>();
+                Image::from_dots(&rotated, width)
+            }
+            3 => {
+                let rotated = (0..width)
+                    .flat_map(|y| (0..height).map(move |x| (x, y)))
+                    .map(|(x, y)| dot(width - 1 - y, x))
+                    .collect::<Vec<_>>();
+                Image::from_dots(&rotated, height)
+            }
+            _ => Image::from_dots(&dots, width),
+        }
+    }
+
+    /// Crops the image to the rectangle of `w` by `h` dots with its top-left
+    /// corner at dot `(x, y)`, repacking the cropped dots into points.
+    ///
+    /// The rectangle is clamped to the bounds of the image, so a rectangle
+    /// that is entirely out of range yields an empty image.
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Image {
+        let (width, dots) = (self.dimensions().0, self.to_dots());
+        let height = dots.len().checked_div(width).unwrap_or(0);
+        let (x, y) = (x.min(width), y.min(height));
+        let (w, h) = (w.min(width - x), h.min(height - y));
+
+        let cropped = dots
+            .chunks(width.max(1))
+            .skip(y)
+            .take(h)
+            .flat_map(|row| &row[x..][..w])
+            .copied()
+            .collect::<Vec<_>>();
+        Image::from_dots(&cropped, w)
+    }
+
+    /// Sets every dot in the rectangle of `w` by `h` dots with its top-left
+    /// corner at dot `(x, y)` to `dot`, clamping the rectangle to the bounds
+    /// of the image.
+    pub fn fill(&self, x: usize, y: usize, w: usize, h: usize, dot: Dot) -> Image {
+        let (width, mut dots) = (self.dimensions().0, self.to_dots());
+        for row in dots.chunks_mut(width.max(1)).skip(y).take(h) {
+            for d in row.iter_mut().skip(x).take(w) {
+                *d = dot;
+            }
+        }
+        Image::from_dots(&dots, width)
+    }
+
+    /// Pads the image on the right and at the bottom with `dot` to `width` by
+    /// `height` dots. Dimensions that are already larger are left as is.
+    pub fn pad(&self, width: usize, height: usize, dot: Dot) -> Image {
+        let (columns, dots) = (self.dimensions().0, self.to_dots());
+        let rows = dots.len().checked_div(columns).unwrap_or(0);
+        let (width, height) = (width.max(columns), height.max(rows));
+
+        let mut padded = Vec::with_capacity(width * height);
+        for row in dots.chunks(columns.max(1)) {
+            padded.extend_from_slice(row);
+            padded.extend(core::iter::repeat_n(dot, width - columns));
+        }
+        padded.resize(width * height, dot);
+        Image::from_dots(&padded, width)
+    }
+
+    /// Scales the image up by repeating every dot `factor` times both
+    /// horizontally and vertically, where a `factor` of 0 is treated as 1.
+    pub fn scale(&self, factor: usize) -> Image {
+        let (width, dots) = (self.dimensions().0, self.to_dots());
+        let factor = factor.max(1);
+
+        let scaled = dots
+            .chunks(width.max(1))
+            .flat_map(|row| {
+                let line = row
+                    .iter()
+                    .flat_map(|&dot| core::iter::repeat_n(dot, factor))
+                    .collect::<Vec<_>>();
+                core::iter::repeat_n(line, factor).flatten()
+            })
+            .collect::<Vec<_>>();
+        Image::from_dots(&scaled, width * factor)
+    }
+
+    /// Places another image to the right of this one, separated by `gap`
+    /// columns of white dots. The shorter of the two images is padded with
+    /// white dots at the bottom, which may add or remove a last half line when
+    /// the images have different heights.
+    pub fn concat_horizontal(&self, other: &Image, gap: usize) -> Image {
+        let (left_width, left_dots) = (self.dimensions().0, self.to_dots());
+        let (right_width, right_dots) = (other.dimensions().0, other.to_dots());
+        let left_rows = left_dots.len().checked_div(left_width).unwrap_or(0);
+        let right_rows = right_dots.len().checked_div(right_width).unwrap_or(0);
+
+        let width = left_width + gap + right_width;
+        if width == 0 {
+            return Image::from_dots(&[], 0);
+        }
+        let rows = left_rows.max(right_rows);
+        let mut dots = vec![Dot::White; width * rows];
+        for (row, line) in dots.chunks_mut(width).enumerate() {
+            if row < left_rows {
+                line[..left_width].copy_from_slice(&left_dots[row * left_width..][..left_width]);
+            }
+            if row < right_rows {
+                line[left_width + gap..]
+                    .copy_from_slice(&right_dots[row * right_width..][..right_width]);
+            }
+        }
+
+        Image::from_dots(&dots, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Dot::{Black as B, White as W};
+
+    #[test]
+    fn point_to_ansi() {
+        let point = Point { top: B, bot: W };
+        assert_eq!(
+            point.to_ansi(Rgb(1, 2, 3), Rgb(4, 5, 6)),
+            "\x1b[38;2;1;2;3m\x1b[48;2;4;5;6m▀",
+        );
+    }
+
+    #[test]
+    fn half_point_to_ansi() {
+        assert_eq!(
+            HalfPoint(B).to_ansi(Rgb::BLACK, Rgb::WHITE),
+            "\x1b[38;2;0;0;0m\x1b[49m▀",
+        );
+        assert_eq!(
+            HalfPoint(W).to_ansi(Rgb::BLACK, Rgb::WHITE),
+            "\x1b[38;2;255;255;255m\x1b[49m▀",
+        );
+    }
+
+    #[test]
+    fn visit_in_row_major_order() {
+        let image = Image::from_dots(&[B, W, W, B, B, B, W, W, B, W], 2);
+        let mut visited = Vec::new();
+        image.visit(|row, column, point| visited.push((row, column, *point)));
+        assert_eq!(
+            visited,
+            [
+                (0, 0, Point { top: B, bot: W }),
+                (0, 1, Point { top: W, bot: B }),
+                (1, 0, Point { top: B, bot: W }),
+                (1, 1, Point { top: B, bot: W }),
+            ],
+        );
+
+        let mut half = Vec::new();
+        image.visit_half_line(|column, point| half.push((column, *point)));
+        assert_eq!(half, [(0, HalfPoint(B)), (1, HalfPoint(W))]);
+    }
+
+    #[test]
+    fn visit_half_line_without_half_line() {
+        let image = Image::from_dots(&[B; 4], 2);
+        let mut count = 0;
+        image.visit_half_line(|_, _| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn checkerboard_alternates_dots() {
+        let image = Image::checkerboard(3, 3, B, W);
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.to_dots(), [B, W, B, W, B, W, B, W, B]);
+        assert_eq!(
+            image.row(0),
+            [
+                Point { top: B, bot: W },
+                Point { top: W, bot: B },
+                Point { top: B, bot: W }
+            ]
+        );
+
+        let inverted = Image::checkerboard(2, 2, W, B);
+        assert_eq!(inverted.to_dots(), [W, B, B, W]);
+    }
+
+    #[test]
+    fn rotate_swaps_dimensions_and_moves_corner() {
+        let image = Image::from_dots(&[B, W, W, W, W, W], 3);
+        assert_eq!(image.dimensions(), (3, 1));
+        assert_eq!(image.rotate(0).to_dots(), image.to_dots());
+        assert_eq!(image.rotate(4).to_dots(), image.to_dots());
+
+        let quarter = image.rotate(1);
+        assert_eq!(quarter.dimensions(), (2, 2));
+        assert_eq!(quarter.to_dots(), [W, B, W, W, W, W]);
+        assert_eq!(image.rotate(2).to_dots(), [W, W, W, W, W, B]);
+        assert_eq!(image.rotate(3).to_dots(), [W, W, W, W, B, W]);
+        assert_eq!(image.rotate(3).dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn crop_rectangle() {
+        #[rustfmt::skip]
+        let image = Image::from_dots(&[
+            B, W, B,
+            W, B, W,
+            B, B, W,
+        ], 3);
+        let cropped = image.crop(1, 1, 2, 2);
+        assert_eq!(cropped.dimensions(), (2, 1));
+        assert_eq!(cropped.to_dots(), [B, W, B, W]);
+
+        let clamped = image.crop(1, 2, 5, 5);
+        assert_eq!(clamped.to_dots(), [B, W]);
+        assert_eq!(clamped.last_line(), Some(&[HalfPoint(B), HalfPoint(W)][..]));
+
+        assert_eq!(image.crop(3, 0, 2, 2).to_dots(), []);
+        assert_eq!(image.crop(0, 3, 2, 2).to_dots(), []);
+    }
+
+    #[test]
+    fn scale_repeats_dots() {
+        let image = Image::from_dots(&[B, W], 2);
+        let scaled = image.scale(2);
+        assert_eq!(scaled.dimensions(), (4, 1));
+        assert_eq!(scaled.to_dots(), [B, B, W, W, B, B, W, W]);
+        assert_eq!(image.scale(0), image);
+    }
+
+    #[test]
+    fn concat_horizontal_dimensions() {
+        let left = Image::from_dots(&[B; 3 * 4], 3);
+        let right = Image::from_dots(&[B; 2 * 5], 2);
+        assert_eq!(left.concat_horizontal(&right, 1).dimensions(), (6, 3));
+        assert_eq!(right.concat_horizontal(&left, 0).dimensions(), (5, 3));
+    }
+
+    #[test]
+    fn concat_horizontal_content() {
+        let left = Image::from_dots(&[B, W, W, B], 2);
+        let right = Image::from_dots(&[B, B, B], 1);
+        let combined = left.concat_horizontal(&right, 1);
+        assert_eq!(combined.dimensions(), (4, 2));
+        assert_eq!(combined.to_dots(), [B, W, W, B, W, B, W, B, W, W, W, B]);
+        assert_eq!(
+            combined.last_line(),
+            Some(&[HalfPoint(W), HalfPoint(W), HalfPoint(W), HalfPoint(B)][..]),
+        );
+    }
+
+    #[test]
+    fn fill_rectangle() {
+        let image = Image::from_dots(&[B; 12], 4);
+        assert_eq!(
+            image.fill(1, 1, 2, 5, W).to_dots(),
+            [B, B, B, B, B, W, W, B, B, W, W, B],
+        );
+        assert_eq!(image.fill(3, 2, 9, 9, W).to_dots()[8..], [B, B, B, W]);
+        assert_eq!(image.fill(4, 0, 1, 1, W), image);
+    }
+
+    #[test]
+    fn pad_to_dimensions() {
+        let image = Image::from_dots(&[B, B, B, B], 2);
+        assert_eq!(image.pad(3, 3, W).to_dots(), [B, B, W, B, B, W, W, W, W],);
+        assert_eq!(image.pad(1, 1, W), image);
+        assert_eq!(Image::from_dots(&[], 0).pad(1, 2, B).to_dots(), [B, B]);
+    }
+
+    #[test]
+    fn concat_horizontal_empty() {
+        let empty = Image::from_dots(&[], 0);
+        assert_eq!(empty.concat_horizontal(&empty, 0).dimensions(), (0, 0));
+        let image = Image::from_dots(&[B, W], 2);
+        assert_eq!(empty.concat_horizontal(&image, 0).to_dots(), [B, W]);
+    }
+}
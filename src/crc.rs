@@ -0,0 +1,66 @@
+//! Application level payload checksums. These complement the QR code's own
+//! error correction, allowing a decoder to verify the integrity of a payload
+//! scanned from a worn or damaged print.
+//!
+//! A checksummed payload is the original payload followed by a suffix made up
+//! of [`SEPARATOR`] and the CRC32 of the original payload as 8 lowercase
+//! hexadecimal digits, for example `hello~crc32:3610a686`.
+
+use crate::error::{QrTermError, Result};
+
+/// The separator between the payload and its checksum.
+pub const SEPARATOR: &[u8] = b"~crc32:";
+
+/// The length of the checksum suffix in bytes.
+const SUFFIX_LEN: usize = SEPARATOR.len() + 8;
+
+/// Appends the CRC32 checksum suffix to a payload.
+pub fn append(data: &[u8]) -> Vec<u8> {
+    let mut payload = data.to_vec();
+    payload.extend_from_slice(SEPARATOR);
+    payload.extend_from_slice(format!("{:08x}", crc32fast::hash(data)).as_bytes());
+    payload
+}
+
+/// Verifies the CRC32 checksum suffix of a payload, returning the original
+/// payload with the suffix removed.
+pub fn verify(payload: &[u8]) -> Result<&[u8]> {
+    let split = payload
+        .len()
+        .checked_sub(SUFFIX_LEN)
+        .ok_or(QrTermError::ChecksumMismatch)?;
+    let (data, suffix) = payload.split_at(split);
+    if append(data)[split..] != *suffix {
+        return Err(QrTermError::ChecksumMismatch);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_and_verify_round_trip() {
+        let payload = append(b"hello");
+        assert_eq!(payload, b"hello~crc32:3610a686");
+        assert_eq!(verify(&payload).unwrap(), b"hello");
+        assert_eq!(verify(&append(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn verify_mismatch() {
+        for payload in [
+            &b"hellp~crc32:3610a686"[..],
+            b"hello~crc32:3610a687",
+            b"hello~crc64:3610a686",
+            b"~crc32:3610a68",
+            b"",
+        ] {
+            assert!(matches!(
+                verify(payload),
+                Err(QrTermError::ChecksumMismatch)
+            ));
+        }
+    }
+}
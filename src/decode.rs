@@ -0,0 +1,120 @@
+//! Decoding of QR codes, used for verifying rendered images and for scanning
+//! codes from image files.
+
+use crate::error::{QrTermError, Result};
+use crate::image::{Dot, Image};
+use rqrr::PreparedImage;
+
+/// The number of pixels per dot when scanning a rendered image. Scaling up the
+/// image makes detection more reliable, as the scanner locates finder
+/// patterns by their proportions in pixels.
+const SCAN_SCALE: usize = 4;
+
+/// Scans a rendered image for a QR code, treating `dark` dots as dark
+/// modules, and returns its decoded payload.
+pub fn scan(image: &Image, dark: Dot) -> Result<Vec<u8>> {
+    let (width, _) = image.dimensions();
+    let dots = image.to_dots();
+    let height = dots.len().checked_div(width).unwrap_or(0);
+
+    let mut prepared =
+        PreparedImage::prepare_from_bitmap(width * SCAN_SCALE, height * SCAN_SCALE, |x, y| {
+            dots[(y / SCAN_SCALE) * width + x / SCAN_SCALE] == dark
+        });
+    let grids = prepared.detect_grids();
+    let grid = grids.first().ok_or(QrTermError::NoCodeFound)?;
+
+    let mut payload = Vec::new();
+    grid.decode_to(&mut payload)
+        .map_err(|err| QrTermError::Decode(err.to_string()))?;
+    Ok(payload)
+}
+
+/// Scans an encoded image file, such as a PNG or JPEG photo or screenshot, for
+/// a QR code and returns its decoded payload.
+pub fn scan_file(bytes: &[u8]) -> Result<Vec<u8>> {
+    let image = ::image::load_from_memory(bytes)
+        .map_err(|err| QrTermError::Decode(err.to_string()))?
+        .to_luma8();
+
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut prepared = PreparedImage::prepare_from_greyscale(width, height, |x, y| {
+        image.get_pixel(x as u32, y as u32).0[0]
+    });
+    let grids = prepared.detect_grids();
+    let grid = grids.first().ok_or(QrTermError::NoCodeFound)?;
+
+    let mut payload = Vec::new();
+    grid.decode_to(&mut payload)
+        .map_err(|err| QrTermError::Decode(err.to_string()))?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcode::QrCode;
+
+    #[test]
+    fn scan_rendered_code() {
+        let code = QrCode::new(b"hello").unwrap();
+        let image = code.render::<Dot>().build();
+        assert_eq!(scan(&image, Dot::Black).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn scan_inverted_code() {
+        let code = QrCode::new(b"hello").unwrap();
+        let image = code
+            .render::<Dot>()
+            .dark_color(Dot::White)
+            .light_color(Dot::Black)
+            .build();
+        assert_eq!(scan(&image, Dot::White).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn scan_corrupted_code_fails() {
+        let code = QrCode::new(b"hello").unwrap();
+        let image = code.render::<Dot>().build();
+        let (width, _) = image.dimensions();
+
+        // Flip the bottom right quadrant of data modules, which is more than
+        // the error correction can recover from.
+        let mut dots = image.to_dots();
+        for y in 13..25 {
+            for x in 13..25 {
+                let dot = &mut dots[y * width + x];
+                *dot = match dot {
+                    Dot::Black => Dot::White,
+                    Dot::White => Dot::Black,
+                };
+            }
+        }
+        let corrupted = Image::from_dots(&dots, width);
+        assert!(scan(&corrupted, Dot::Black).is_err());
+    }
+
+    #[test]
+    fn scan_png_file() {
+        use crate::backend::{Backend, Png};
+
+        let code = QrCode::new(b"hello").unwrap();
+        let image = code.render::<Dot>().build();
+        let mut png = Vec::new();
+        Png {
+            scale: 4,
+            transparent: false,
+            logo: None,
+        }
+        .write(&image, &mut png)
+        .unwrap();
+        assert_eq!(scan_file(&png).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn scan_invalid_file_fails() {
+        let err = scan_file(b"not an image").err();
+        assert!(matches!(err, Some(QrTermError::Decode(_))));
+    }
+}
@@ -0,0 +1,590 @@
+//! Encoding of data into QR codes.
+
+use crate::error::{QrTermError, Result};
+use log::{debug, log_enabled, Level};
+use qrcode::bits::{self, Bits};
+use qrcode::canvas::Canvas;
+use qrcode::ec;
+use qrcode::optimize::{Parser, Segment};
+use qrcode::types::{Mode, QrError};
+use qrcode::{Color, EcLevel, QrCode, Version};
+
+/// Options for encoding data into QR codes.
+#[derive(Clone, Copy, Debug)]
+pub struct EncodeOptions {
+    /// The minimum error correction level to use.
+    pub ec_level: EcLevel,
+    /// Raise the error correction level as long as the data still fits in the
+    /// smallest version selected for the minimum error correction level.
+    pub boost_ecc: bool,
+    /// The smallest (normal) QR code version to use, even if the data fits in
+    /// a smaller one.
+    pub min_version: Option<i16>,
+    /// The exact (normal) QR code version to use, instead of the smallest one
+    /// that fits the data.
+    pub version: Option<i16>,
+    /// The mode to encode all of the data in, instead of optimally splitting
+    /// it into segments with different modes.
+    pub mode: Option<Mode>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            ec_level: EcLevel::M,
+            boost_ecc: true,
+            min_version: None,
+            version: None,
+            mode: None,
+        }
+    }
+}
+
+/// Encodes data into a QR code.
+pub fn encode(data: &[u8], options: &EncodeOptions) -> Result<QrCode> {
+    if data.is_empty() {
+        return Err(QrTermError::EmptyData);
+    }
+    for v in options.version.iter().chain(&options.min_version) {
+        if !(1..=40).contains(v) {
+            return Err(QrTermError::InvalidVersion);
+        }
+    }
+
+    let bits = match (options.version, options.min_version, options.mode) {
+        (Some(v), _, mode) => encode_bits(data, Version::Normal(v), mode, options.ec_level)?,
+        (None, None, None) => bits::encode_auto(data, options.ec_level)?,
+        (None, min, mode) => {
+            let min = min.unwrap_or(1);
+            (min..=40)
+                .map(|v| encode_bits(data, Version::Normal(v), mode, options.ec_level))
+                .find(|bits| !matches!(bits, Err(QrError::DataTooLong)))
+                .unwrap_or(Err(QrError::DataTooLong))?
+        }
+    };
+    let version = bits.version();
+    if options.mode.is_none() && log_enabled!(Level::Debug) {
+        for segment in Parser::new(data).optimize(version) {
+            debug!(
+                "segment {:?}[{}..{}]",
+                segment.mode, segment.begin, segment.end,
+            );
+        }
+    }
+    debug!(
+        "selected version {:?} for error correction level {:?}",
+        version, options.ec_level,
+    );
+
+    let mut bits = bits;
+    let mut ec_level = options.ec_level;
+    if options.boost_ecc {
+        for &boosted in &[EcLevel::H, EcLevel::Q, EcLevel::M] {
+            if boosted <= ec_level {
+                break;
+            }
+            if let Ok(boosted_bits) = encode_bits(data, version, options.mode, boosted) {
+                debug!("boosted error correction level to {:?}", boosted);
+                bits = boosted_bits;
+                ec_level = boosted;
+                break;
+            }
+        }
+    }
+
+    let code = QrCode::with_bits(bits, ec_level)?;
+    debug!("applied mask pattern {}", mask(&code));
+    Ok(code)
+}
+
+/// Encodes data into bits for the specified version and error correction
+/// level, either in a single mode or optimally split into segments.
+fn encode_bits(
+    data: &[u8],
+    version: Version,
+    mode: Option<Mode>,
+    ec_level: EcLevel,
+) -> Result<Bits, QrError> {
+    let mut bits = Bits::new(version);
+    push_data(&mut bits, data, mode)?;
+    bits.push_terminator(ec_level)?;
+    Ok(bits)
+}
+
+/// Pushes data to the bits, either in a single mode or optimally split into
+/// segments.
+fn push_data(bits: &mut Bits, data: &[u8], mode: Option<Mode>) -> Result<(), QrError> {
+    // The `qrcode` crate does not validate the characters of data pushed in a
+    // specific mode, so check them up front.
+    let valid = match mode {
+        Some(Mode::Numeric) => data.iter().all(u8::is_ascii_digit),
+        Some(Mode::Alphanumeric) => data
+            .iter()
+            .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%*+-./:".contains(b)),
+        _ => true,
+    };
+    if !valid {
+        return Err(QrError::InvalidCharacter);
+    }
+
+    match mode {
+        None => bits.push_optimal_data(data),
+        Some(Mode::Numeric) => bits.push_numeric_data(data),
+        Some(Mode::Alphanumeric) => bits.push_alphanumeric_data(data),
+        Some(Mode::Byte) => bits.push_byte_data(data),
+        Some(Mode::Kanji) => bits.push_kanji_data(data),
+    }
+}
+
+/// The maximum number of QR codes in a structured append sequence.
+pub const MAX_STRUCTURED_APPEND_PARTS: usize = 16;
+
+/// A QR code that is part of a structured append sequence.
+#[derive(Clone, Debug)]
+pub struct Part {
+    /// The modules of the QR code in row-major order.
+    pub colors: Vec<Color>,
+    /// The version of the QR code.
+    pub version: Version,
+    /// The error correction level of the QR code.
+    pub ec_level: EcLevel,
+    /// The number of bytes of the data in this part.
+    pub len: usize,
+}
+
+impl Part {
+    /// Returns the width of the QR code in modules, excluding the quiet zone.
+    pub fn width(&self) -> usize {
+        self.version.width() as usize
+    }
+}
+
+/// Splits data across the fewest QR codes of at most `max_version` that
+/// fit it, combined with the structured append mode so that scanners that
+/// support it can reassemble the data.
+///
+/// The exact version, minimum version, mode and error correction options are
+/// applied to each part. Returns [`QrTermError::DataTooLong`] if the data
+/// does not fit in [`MAX_STRUCTURED_APPEND_PARTS`] QR codes.
+pub fn split(data: &[u8], options: &EncodeOptions, max_version: i16) -> Result<Vec<Part>> {
+    if data.is_empty() {
+        return Err(QrTermError::EmptyData);
+    }
+    let versions = match options.version {
+        Some(version) => version..=version,
+        None => options.min_version.unwrap_or(1)..=max_version,
+    };
+    if !(1..=40).contains(versions.start()) || !(1..=40).contains(versions.end()) {
+        return Err(QrTermError::InvalidVersion);
+    }
+
+    if let Err(QrError::InvalidCharacter) =
+        push_data(&mut Bits::new(Version::Normal(40)), data, options.mode)
+    {
+        return Err(QrTermError::InvalidCharacter);
+    }
+
+    let parity = data.iter().fold(0, |parity, byte| parity ^ byte);
+    for count in 1..=MAX_STRUCTURED_APPEND_PARTS {
+        let chunks = data.chunks(data.len().div_ceil(count)).collect::<Vec<_>>();
+        let fitted = chunks
+            .iter()
+            .map(|chunk| {
+                versions
+                    .clone()
+                    .find_map(|v| structured_bits(chunk, Version::Normal(v), options).ok())
+            })
+            .collect::<Option<Vec<_>>>();
+        let fitted = match fitted {
+            Some(fitted) => fitted,
+            None => continue,
+        };
+        debug!("split {} bytes into {} parts", data.len(), chunks.len());
+
+        return fitted
+            .into_iter()
+            .zip(&chunks)
+            .enumerate()
+            .map(|(index, ((segments, version, ec_level), chunk))| {
+                // The structured append header contains the 4-bit position
+                // of the part, the 4-bit index of the last part and a parity
+                // byte of the entire data.
+                let mut stream = BitStream::default();
+                stream.push(0b0011, 4);
+                stream.push(index as u32, 4);
+                stream.push(chunks.len() as u32 - 1, 4);
+                stream.push(parity as u32, 8);
+                let segments_len = segments.len();
+                for (i, byte) in segments.into_bytes().into_iter().enumerate() {
+                    let bits = (segments_len - i * 8).min(8);
+                    stream.push((byte >> (8 - bits)) as u32, bits);
+                }
+
+                let capacity = Bits::new(version).max_len(ec_level)?;
+                let codewords = stream.finish(capacity);
+                let (data, ec) = ec::construct_codewords(&codewords, version, ec_level)?;
+                let mut canvas = Canvas::new(version, ec_level);
+                canvas.draw_all_functional_patterns();
+                canvas.draw_data(&data, &ec);
+                Ok(Part {
+                    colors: canvas.apply_best_mask().into_colors(),
+                    version,
+                    ec_level,
+                    len: chunk.len(),
+                })
+            })
+            .collect();
+    }
+    Err(QrTermError::DataTooLong)
+}
+
+/// Encodes a part of a structured append sequence for the specified version,
+/// returning the data segments without a terminator along with the version
+/// and the (possibly boosted) error correction level, or an error if it does
+/// not fit along with the structured append header.
+fn structured_bits(
+    data: &[u8],
+    version: Version,
+    options: &EncodeOptions,
+) -> Result<(Bits, Version, EcLevel), QrError> {
+    // The mode indicator, position and parity of the structured append
+    // header.
+    const HEADER_LEN: usize = 20;
+
+    let mut bits = Bits::new(version);
+    push_data(&mut bits, data, options.mode)?;
+
+    let fits = |ec_level| {
+        Bits::new(version)
+            .max_len(ec_level)
+            .map(|capacity| bits.len() + HEADER_LEN <= capacity)
+            .unwrap_or(false)
+    };
+    if !fits(options.ec_level) {
+        return Err(QrError::DataTooLong);
+    }
+    let ec_level = if options.boost_ecc {
+        [EcLevel::H, EcLevel::Q, EcLevel::M]
+            .iter()
+            .copied()
+            .filter(|&boosted| boosted > options.ec_level)
+            .find(|&boosted| fits(boosted))
+            .unwrap_or(options.ec_level)
+    } else {
+        options.ec_level
+    };
+    Ok((bits, version, ec_level))
+}
+
+/// A stream of bits for building data codewords by hand.
+#[derive(Default)]
+struct BitStream {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitStream {
+    /// Pushes the `count` least significant bits of `value`, from the most to
+    /// the least significant bit.
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+
+    /// Terminates the stream and pads it to the capacity in bits, as
+    /// specified by ISO/IEC 18004, returning the data codewords.
+    fn finish(mut self, capacity: usize) -> Vec<u8> {
+        self.push(0, (capacity - self.len).min(4));
+        self.push(0, (8 - self.len % 8) % 8);
+        for &padding in [0xec, 0x11].iter().cycle() {
+            if self.len >= capacity {
+                break;
+            }
+            self.push(padding, 8);
+        }
+        self.bytes
+    }
+}
+
+/// Reads the data mask pattern reference (from 0 to 7) applied to a QR code
+/// from its format information.
+pub fn mask(code: &QrCode) -> u8 {
+    // The coordinates of the format information bits next to the top-left
+    // finder pattern, from the most to the least significant bit.
+    const FORMAT_INFO_COORDS: [(usize, usize); 15] = [
+        (0, 8),
+        (1, 8),
+        (2, 8),
+        (3, 8),
+        (4, 8),
+        (5, 8),
+        (7, 8),
+        (8, 8),
+        (8, 7),
+        (8, 5),
+        (8, 4),
+        (8, 3),
+        (8, 2),
+        (8, 1),
+        (8, 0),
+    ];
+    const FORMAT_INFO_MASK: u16 = 0x5412;
+
+    let format_info = FORMAT_INFO_COORDS.iter().fold(0, |bits, &coord| {
+        (bits << 1) | (code[coord] == Color::Dark) as u16
+    });
+    (((format_info ^ FORMAT_INFO_MASK) >> 10) & 0b111) as u8
+}
+
+/// Computes the version that would be selected for encoding data at the
+/// specified error correction level, along with the resulting code width in
+/// modules (excluding the quiet zone), without building the QR code.
+pub fn plan(data: &[u8], ec: EcLevel) -> Result<(Version, usize)> {
+    if data.is_empty() {
+        return Err(QrTermError::EmptyData);
+    }
+    let version = bits::encode_auto(data, ec)?.version();
+    Ok((version, version.width() as usize))
+}
+
+/// Returns the segmentation of the data for the specified version along with
+/// the number of bits each segment costs to encode, including its mode
+/// indicator and character count. The data is a single segment in the
+/// specified mode like [`encode`] does, or optimally split into segments.
+pub fn segments(data: &[u8], version: Version, mode: Option<Mode>) -> Vec<(Segment, usize)> {
+    let segments = match mode {
+        Some(mode) => vec![Segment {
+            mode,
+            begin: 0,
+            end: data.len(),
+        }],
+        None => Parser::new(data).optimize(version).collect(),
+    };
+    segments
+        .into_iter()
+        .map(|segment| (segment, segment.encoded_len(version)))
+        .collect()
+}
+
+/// Computes the number of data bits used by the payload and the total data
+/// capacity in bits of a QR code with the specified version and error
+/// correction level, as a `(used, capacity)` tuple. The payload is encoded in
+/// the specified mode like [`encode`] does, or optimally split into segments.
+pub fn utilization(
+    data: &[u8],
+    version: Version,
+    ec: EcLevel,
+    mode: Option<Mode>,
+) -> Result<(usize, usize)> {
+    let mut bits = Bits::new(version);
+    push_data(&mut bits, data, mode)?;
+    let capacity = bits.max_len(ec)?;
+    Ok((bits.len(), capacity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_data_is_an_error() {
+        let err = encode(b"", &EncodeOptions::default()).err();
+        assert!(matches!(err, Some(QrTermError::EmptyData)));
+    }
+
+    #[test]
+    fn oversized_data_is_an_error() {
+        let data = vec![b'x'; 4000];
+        let err = encode(&data, &EncodeOptions::default()).err();
+        assert!(matches!(err, Some(QrTermError::DataTooLong)));
+    }
+
+    #[test]
+    fn plan_selects_smallest_version() {
+        let cases: &[(&[u8], EcLevel, i16)] = &[
+            (b"hello world", EcLevel::M, 1),
+            (&[b'a'; 20], EcLevel::M, 2),
+            (&[b'a'; 40], EcLevel::M, 3),
+            (&[b'1'; 41], EcLevel::L, 1),
+            (&[b'1'; 42], EcLevel::L, 2),
+        ];
+        for &(data, ec, version) in cases {
+            let expected = Version::Normal(version);
+            assert_eq!(
+                plan(data, ec).unwrap(),
+                (expected, expected.width() as usize)
+            );
+        }
+        assert!(matches!(plan(b"", EcLevel::M), Err(QrTermError::EmptyData)));
+    }
+
+    #[test]
+    fn utilization_of_known_payload() {
+        // A 4-bit mode indicator, an 8-bit character count and 11 bytes of
+        // data, out of the 16 data codewords of a version 1-M code.
+        assert_eq!(
+            utilization(b"hello world", Version::Normal(1), EcLevel::M, None).unwrap(),
+            (100, 128),
+        );
+    }
+
+    #[test]
+    fn utilization_in_forced_mode() {
+        // Optimally encoded as alphanumeric, with a 9-bit character count and
+        // 11 bits per pair of characters, unless byte mode is forced.
+        let data = b"HELLO WORLD";
+        let version = Version::Normal(1);
+        assert_eq!(
+            utilization(data, version, EcLevel::M, None).unwrap(),
+            (74, 128)
+        );
+        assert_eq!(
+            utilization(data, version, EcLevel::M, Some(Mode::Byte)).unwrap(),
+            (100, 128),
+        );
+        assert!(utilization(data, version, EcLevel::M, Some(Mode::Numeric)).is_err());
+    }
+
+    #[test]
+    fn segments_of_mixed_payload() {
+        let segments = |mode| {
+            segments(b"0123456789hello", Version::Normal(1), mode)
+                .into_iter()
+                .map(|(segment, bits)| (segment.mode, segment.begin, segment.end, bits))
+                .collect::<Vec<_>>()
+        };
+        // Numeric segments cost 10 bits per 3 digits and byte segments 8 bits
+        // per byte, each with a 4-bit mode indicator and a character count of
+        // 10 and 8 bits respectively.
+        assert_eq!(
+            segments(None),
+            [(Mode::Numeric, 0, 10, 48), (Mode::Byte, 10, 15, 52)]
+        );
+        assert_eq!(segments(Some(Mode::Byte)), [(Mode::Byte, 0, 15, 132)]);
+    }
+
+    #[test]
+    fn boost_ecc_raises_level_with_spare_capacity() {
+        // The 100 bits of data fit in a version 1 code at level Q, but not H.
+        let options = EncodeOptions::default();
+        let boosted = encode(b"hello world", &options).unwrap();
+        let plain = encode(
+            b"hello world",
+            &EncodeOptions {
+                boost_ecc: false,
+                ..options
+            },
+        )
+        .unwrap();
+
+        assert_eq!(plain.version(), Version::Normal(1));
+        assert_eq!(boosted.version(), Version::Normal(1));
+        assert_eq!(plain.error_correction_level(), EcLevel::M);
+        assert_eq!(boosted.error_correction_level(), EcLevel::Q);
+    }
+
+    #[test]
+    fn unboosted_options_match_library() {
+        let options = EncodeOptions {
+            boost_ecc: false,
+            ..EncodeOptions::default()
+        };
+        for data in [&b"hello"[..], b"0123456789", b"HTTPS://EXAMPLE.COM/12345"] {
+            assert_eq!(
+                encode(data, &options).unwrap().to_colors(),
+                QrCode::new(data).unwrap().to_colors(),
+            );
+        }
+    }
+
+    #[test]
+    fn min_version_sets_a_floor() {
+        let options = |min_version| EncodeOptions {
+            min_version,
+            ..EncodeOptions::default()
+        };
+        let floored = encode(b"hello", &options(Some(5))).unwrap();
+        assert_eq!(floored.version(), Version::Normal(5));
+        assert_eq!(floored.width(), 37);
+
+        let data = vec![b'a'; 100];
+        let larger = encode(&data, &options(Some(2))).unwrap();
+        assert!(larger.width() > Version::Normal(2).width() as usize);
+        assert_eq!(
+            larger.version(),
+            encode(&data, &options(None)).unwrap().version()
+        );
+
+        for invalid in [0, 41] {
+            let err = encode(b"hello", &options(Some(invalid))).err();
+            assert!(matches!(err, Some(QrTermError::InvalidVersion)));
+        }
+    }
+
+    #[test]
+    fn exact_version_and_mode() {
+        let options = |version, mode| EncodeOptions {
+            version,
+            mode,
+            ..EncodeOptions::default()
+        };
+
+        let exact = encode(b"hello", &options(Some(3), None)).unwrap();
+        assert_eq!(exact.version(), Version::Normal(3));
+        let err = encode(&[b'a'; 100], &options(Some(1), None)).err();
+        assert!(matches!(err, Some(QrTermError::DataTooLong)));
+
+        let digits = [b'1'; 41];
+        let numeric = encode(&digits, &options(None, Some(Mode::Numeric))).unwrap();
+        let byte = encode(&digits, &options(None, Some(Mode::Byte))).unwrap();
+        assert!(numeric.width() < byte.width());
+
+        encode(b"HELLO", &options(None, Some(Mode::Alphanumeric))).unwrap();
+        for (data, mode) in [(&b"12a"[..], Mode::Numeric), (b"hello", Mode::Alphanumeric)] {
+            let err = encode(data, &options(None, Some(mode))).err();
+            assert!(matches!(err, Some(QrTermError::InvalidCharacter)));
+        }
+    }
+
+    #[test]
+    fn split_into_structured_append_parts() {
+        let options = EncodeOptions::default();
+        let single = split(b"hello", &options, 40).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].version, Version::Normal(1));
+
+        let data = vec![b'a'; 300];
+        let parts = split(&data, &options, 5).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts.iter().map(|part| part.len).sum::<usize>(), 300);
+        for part in &parts {
+            assert!(part.width() <= Version::Normal(5).width() as usize);
+            assert_eq!(part.colors.len(), part.width() * part.width());
+        }
+    }
+
+    #[test]
+    fn split_errors() {
+        let options = EncodeOptions::default();
+        let err = split(b"", &options, 40).err();
+        assert!(matches!(err, Some(QrTermError::EmptyData)));
+        let err = split(&[b'a'; 5000], &options, 5).err();
+        assert!(matches!(err, Some(QrTermError::DataTooLong)));
+        let err = split(b"hello", &options, 41).err();
+        assert!(matches!(err, Some(QrTermError::InvalidVersion)));
+
+        let numeric = EncodeOptions {
+            mode: Some(Mode::Numeric),
+            ..options
+        };
+        let err = split(b"12a", &numeric, 40).err();
+        assert!(matches!(err, Some(QrTermError::InvalidCharacter)));
+    }
+}
@@ -0,0 +1,88 @@
+//! Error types for generating and rendering terminal QR codes.
+
+use crate::color::ParseColorError;
+use qrcode::types::QrError;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// An error generating or rendering a terminal QR code.
+#[derive(Debug)]
+pub enum QrTermError {
+    /// There is no data to encode.
+    EmptyData,
+    /// The data is too long to fit in a QR code.
+    DataTooLong,
+    /// The QR code version or error correction level is invalid.
+    InvalidVersion,
+    /// The data contains characters that cannot be encoded by the QR code.
+    InvalidCharacter,
+    /// A colour could not be parsed.
+    InvalidColor(String),
+    /// An invalid combination of options was specified.
+    InvalidOptions(String),
+    /// No QR code was found when scanning.
+    NoCodeFound,
+    /// A scanned QR code could not be decoded.
+    Decode(String),
+    /// A payload checksum is missing or does not match.
+    ChecksumMismatch,
+    /// The buffer provided for rendering is too small for the image.
+    BufferTooSmall,
+    /// An I/O error occurred.
+    Io(io::Error),
+}
+
+/// A result type with a `QrTermError` error.
+pub type Result<T, E = QrTermError> = std::result::Result<T, E>;
+
+impl Display for QrTermError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            QrTermError::EmptyData => f.write_str("empty data"),
+            QrTermError::DataTooLong => f.write_str("data too long"),
+            QrTermError::InvalidVersion => f.write_str("invalid version"),
+            QrTermError::InvalidCharacter => f.write_str("invalid character"),
+            QrTermError::InvalidColor(color) => write!(f, "invalid colour '{}'", color),
+            QrTermError::InvalidOptions(reason) => write!(f, "invalid options: {}", reason),
+            QrTermError::NoCodeFound => f.write_str("no QR code found"),
+            QrTermError::Decode(err) => write!(f, "failed to decode QR code: {}", err),
+            QrTermError::ChecksumMismatch => f.write_str("checksum mismatch"),
+            QrTermError::BufferTooSmall => f.write_str("buffer too small"),
+            QrTermError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for QrTermError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QrTermError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<QrError> for QrTermError {
+    fn from(err: QrError) -> Self {
+        match err {
+            QrError::DataTooLong => QrTermError::DataTooLong,
+            QrError::InvalidVersion => QrTermError::InvalidVersion,
+            QrError::UnsupportedCharacterSet
+            | QrError::InvalidEciDesignator
+            | QrError::InvalidCharacter => QrTermError::InvalidCharacter,
+        }
+    }
+}
+
+impl From<ParseColorError> for QrTermError {
+    fn from(err: ParseColorError) -> Self {
+        QrTermError::InvalidColor(err.0)
+    }
+}
+
+impl From<io::Error> for QrTermError {
+    fn from(err: io::Error) -> Self {
+        QrTermError::Io(err)
+    }
+}
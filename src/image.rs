@@ -1,18 +1,25 @@
-//! Image implementation for rendring QR codes to terminals. Note that we create
-//! a "point" abstraction for representing two QR dots. This is done because
-//! terminal characters are vertical and can display two QR dots. This means
-//! that when rendering, we print out two dots at a time per character.
+//! Image implementation for rendring QR codes to terminals. This module glues
+//! the core rendering types to the `qrcode` renderer and standard I/O.
 
+pub use crate::core::{Charset, Density, Dot, HalfPoint, Image, Point};
+
+use crate::color::{self, ColorDepth, Rgb};
+use crate::error::{QrTermError, Result};
 use qrcode::render::{Canvas, Pixel};
 use qrcode::types::Color;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+impl FromStr for Dot {
+    type Err = QrTermError;
 
-/// A QR dot that can either be white or black.
-#[derive(Clone, Copy)]
-pub enum Dot {
-    /// A black dot.
-    Black,
-    /// A white dot.
-    White,
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "black" => Ok(Dot::Black),
+            "white" => Ok(Dot::White),
+            _ => Err(QrTermError::InvalidColor(s.to_owned())),
+        }
+    }
 }
 
 impl Pixel for Dot {
@@ -31,45 +38,6 @@ impl Pixel for Dot {
     }
 }
 
-/// A rendering character. This is slightly different than a dot as terminal
-/// characters have a height of two dots.
-pub struct Point {
-    pub top: Dot,
-    pub bot: Dot,
-}
-
-impl Point {
-    /// Converts a point to a unicode block character.
-    ///
-    /// Note this method assume `Black` to be filled in, meaning it will look
-    /// "correct" when using a white background and black font colour.
-    pub fn to_char(&self) -> char {
-        match (self.top, self.bot) {
-            (Dot::Black, Dot::Black) => '█',
-            (Dot::Black, Dot::White) => '▀',
-            (Dot::White, Dot::Black) => '▄',
-            (Dot::White, Dot::White) => ' ',
-        }
-    }
-}
-
-/// A half point, when there is an uneven number of rows. The distiction is
-/// important when using 256 colors where the true black is different than
-/// terminal background off-black.
-pub struct HalfPoint(pub Dot);
-
-impl HalfPoint {
-    /// Converts a half point to a unicode block character.
-    ///
-    /// See [`Point::to_char`] for more details.
-    pub fn to_char(&self) -> char {
-        match self.0 {
-            Dot::Black => '▀',
-            Dot::White => ' ',
-        }
-    }
-}
-
 /// A image grid used for rendering.
 pub struct Grid {
     dots: Vec<Dot>,
@@ -102,31 +70,314 @@ impl Canvas for Grid {
     }
 
     fn into_image(self) -> Self::Image {
-        let w = self.width;
-        let h = self.dots.len() / w;
-        let mut lines = Vec::with_capacity(h / 2);
-        let mut last_line = None;
+        Image::from_dots(&self.dots, self.width)
+    }
+}
 
-        for line in self.dots.chunks(w * 2) {
-            if line.len() == w * 2 {
-                lines.push(
-                    line[..w]
-                        .iter()
-                        .zip(&line[w..])
-                        .map(|(&top, &bot)| Point { top, bot })
-                        .collect(),
-                );
-            } else {
-                last_line = Some(line.iter().copied().map(HalfPoint).collect())
+impl Image {
+    /// Writes the image as lines of unicode block characters.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for line in self.rows() {
+            for point in line {
+                write!(out, "{}", point.to_char())?;
+            }
+            writeln!(out)?;
+        }
+        if let Some(last_line) = self.last_line() {
+            for point in last_line {
+                write!(out, "{}", point.to_char())?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image as lines of unicode block characters wrapped in ANSI
+    /// escape sequences, drawing `Black` dots with the `fg` colour and `White`
+    /// dots with the `bg` colour, using the nearest colours available with the
+    /// specified colour depth.
+    ///
+    /// This makes the code render consistently regardless of the terminal's
+    /// theme. Colours are reset at the end of every line.
+    pub fn write_ansi_to<W: Write>(
+        &self,
+        out: &mut W,
+        fg: Rgb,
+        bg: Rgb,
+        depth: ColorDepth,
+    ) -> io::Result<()> {
+        let colors = format!("{}{}", fg.fg_for(depth), bg.bg_for(depth));
+        for line in self.rows() {
+            out.write_all(colors.as_bytes())?;
+            for point in line {
+                write!(out, "{}", point.to_char())?;
+            }
+            writeln!(out, "{}", color::RESET)?;
+        }
+        if let Some(last_line) = self.last_line() {
+            // The bottom half of the last line is left with the terminal's
+            // default background, so draw the top half of every half point as
+            // an upper half block in the colour of its dot.
+            let (fg, bg) = (fg.fg_for(depth), bg.fg_for(depth));
+            out.write_all(b"\x1b[49m")?;
+            for point in last_line {
+                let color = match point.0 {
+                    Dot::Black => &fg,
+                    Dot::White => &bg,
+                };
+                write!(out, "{}▀", color)?;
+            }
+            writeln!(out, "{}", color::RESET)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image as lines of ASCII characters, for terminals without
+    /// unicode support.
+    ///
+    /// Every line of points is written as two lines of text, using
+    /// [`Point::to_ascii`] for full lines and [`HalfPoint::to_ascii`] for the
+    /// last half line, so the output has one line per row of dots.
+    pub fn write_ascii_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for line in self.rows() {
+            for half in 0..2 {
+                for point in line {
+                    out.write_all(point.to_ascii()[half].as_bytes())?;
+                }
+                writeln!(out)?;
+            }
+        }
+        if let Some(last_line) = self.last_line() {
+            for point in last_line {
+                out.write_all(point.to_ascii().as_bytes())?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image packing dots into characters with the specified
+    /// density, optionally coloured as with [`Image::write_ansi_to`] using the
+    /// `(fg, bg, depth)` colours.
+    ///
+    /// Dots past the right or bottom edge of the image needed to fill the
+    /// last column or row of characters are treated as `White` dots.
+    pub fn write_dense_to<W: Write>(
+        &self,
+        out: &mut W,
+        density: Density,
+        colors: Option<(Rgb, Rgb, ColorDepth)>,
+    ) -> io::Result<()> {
+        if density == Density::Half {
+            return match colors {
+                Some((fg, bg, depth)) => self.write_ansi_to(out, fg, bg, depth),
+                None => self.write_to(out),
+            };
+        }
+
+        let (width, dots) = (self.dimensions().0, self.to_dots());
+        let height = dots.len().checked_div(width).unwrap_or(0);
+        let (cell_width, cell_height) = density.cell_size();
+        let (prefix, suffix) = match colors {
+            Some((fg, bg, depth)) => (
+                format!("{}{}", fg.fg_for(depth), bg.bg_for(depth)),
+                color::RESET,
+            ),
+            None => (String::new(), ""),
+        };
+
+        for y in (0..height).step_by(cell_height) {
+            out.write_all(prefix.as_bytes())?;
+            for x in (0..width).step_by(cell_width) {
+                let glyph = density.to_char(|dx, dy| {
+                    let (x, y) = (x + dx, y + dy);
+                    x < width && y < height && dots[x + y * width] == Dot::Black
+                });
+                write!(out, "{}", glyph)?;
+            }
+            writeln!(out, "{}", suffix)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image for photographing and reading back with OCR, with one
+    /// line per row of dots and two characters per dot so that each dot is
+    /// roughly square.
+    ///
+    /// Black dots are drawn as `██` and white dots as `..`. Unlike spaces, the
+    /// full stops are kept by OCR engines, so lines keep their length and
+    /// quiet zone rows are not dropped, while still contrasting clearly with
+    /// the full blocks. The trade-off is that the output is four times the size
+    /// of [`Image::write_to`] and is not directly scannable on screen.
+    pub fn write_ocr_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (columns, _) = self.dimensions();
+        for row in self.to_dots().chunks(columns.max(1)) {
+            for dot in row {
+                let glyph = match dot {
+                    Dot::Black => "██",
+                    Dot::White => "..",
+                };
+                out.write_all(glyph.as_bytes())?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Renders the image into a caller-provided character buffer without
+    /// allocating.
+    ///
+    /// The buffer is laid out in row-major order where each row starts
+    /// `stride` characters after the previous one. For an image with
+    /// dimensions `(columns, rows)`, the `stride` must be at least `columns`
+    /// and the buffer must hold at least `(rows - 1) * stride + columns`
+    /// characters, otherwise [`QrTermError::BufferTooSmall`] is returned.
+    /// Characters in the buffer past the end of each row are left untouched.
+    pub fn render_into(&self, buf: &mut [char], stride: usize) -> Result<()> {
+        let (columns, rows) = self.dimensions();
+        if rows == 0 {
+            return Ok(());
+        }
+        if stride < columns || buf.len() < (rows - 1) * stride + columns {
+            return Err(QrTermError::BufferTooSmall);
+        }
+
+        for (line, row) in self.rows().zip(buf.chunks_mut(stride)) {
+            for (point, cell) in line.iter().zip(row) {
+                *cell = point.to_char();
+            }
+        }
+        if let Some(last_line) = self.last_line() {
+            let row = &mut buf[self.line_count() * stride..];
+            for (point, cell) in last_line.iter().zip(row) {
+                *cell = point.to_char();
             }
         }
 
-        Image { lines, last_line }
+        Ok(())
     }
 }
 
-/// A QR image for rendering to the terminal.
-pub struct Image {
-    pub lines: Vec<Vec<Point>>,
-    pub last_line: Option<Vec<HalfPoint>>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Dot::{Black as B, White as W};
+
+    #[test]
+    fn render_into_buffer() {
+        let image = Image::from_dots(&[B, W, B, B, W, B], 2);
+        let mut buf = ['x'; 6];
+        image.render_into(&mut buf, 3).unwrap();
+        assert_eq!(buf, ['█', '▄', 'x', ' ', '▀', 'x']);
+    }
+
+    #[test]
+    fn write_ocr_rows() {
+        let image = Image::from_dots(&[B, W, B, B, W, B], 2);
+        let mut out = Vec::new();
+        image.write_ocr_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "██..\n████\n..██\n");
+    }
+
+    #[test]
+    fn write_ascii_rows() {
+        let image = Image::from_dots(&[B, W, B, B, W, B], 2);
+        let mut out = Vec::new();
+        image.write_ascii_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "##  \n####\n  ##\n");
+    }
+
+    #[test]
+    fn write_dense_pads_partial_cells() {
+        let image = Image::from_dots(&[B, W, B, W, B, W, B, B, W], 3);
+        let dense = |density| {
+            let mut out = Vec::new();
+            image.write_dense_to(&mut out, density, None).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(dense(Density::Quad), "▚▘\n▀ \n");
+        assert_eq!(dense(Density::Braille), "\u{2835}\u{2801}\n");
+    }
+
+    #[test]
+    fn render_into_too_small_buffer() {
+        let image = Image::from_dots(&[B, W, B, B, W, B], 2);
+        let mut buf = ['x'; 4];
+        assert!(matches!(
+            image.render_into(&mut buf, 3),
+            Err(QrTermError::BufferTooSmall)
+        ));
+        assert!(matches!(
+            image.render_into(&mut buf, 1),
+            Err(QrTermError::BufferTooSmall)
+        ));
+    }
+
+    /// Renders dots with the previous nested `Vec<Vec<Point>>` image layout,
+    /// as a reference for the output of the flat layout.
+    fn write_nested(dots: &[Dot], width: usize) -> Vec<u8> {
+        let mut lines = Vec::new();
+        let mut last_line = None;
+        for line in dots.chunks(width * 2) {
+            if line.len() == width * 2 {
+                let points = line[..width]
+                    .iter()
+                    .zip(&line[width..])
+                    .map(|(&top, &bot)| Point { top, bot })
+                    .collect::<Vec<_>>();
+                lines.push(points);
+            } else {
+                last_line = Some(line.iter().copied().map(HalfPoint).collect::<Vec<_>>());
+            }
+        }
+
+        let mut out = Vec::new();
+        for line in &lines {
+            for point in line {
+                write!(out, "{}", point.to_char()).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        if let Some(last_line) = &last_line {
+            for point in last_line {
+                write!(out, "{}", point.to_char()).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn flat_layout_matches_nested_layout() {
+        let mut seed = 0x2545_f491_u32;
+        for &(width, height) in &[(1, 1), (2, 3), (5, 4), (21, 21), (29, 30), (185, 185)] {
+            let dots = (0..width * height)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    if seed & 1 == 0 {
+                        B
+                    } else {
+                        W
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut flat = Vec::new();
+            Image::from_dots(&dots, width).write_to(&mut flat).unwrap();
+            assert_eq!(flat, write_nested(&dots, width), "{}x{}", width, height);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let image = Image::from_dots(&[B, W, W, B, B, W], 2);
+        let json = serde_json::to_string(&image).unwrap();
+        assert_eq!(serde_json::from_str::<Image>(&json).unwrap(), image);
+
+        assert_eq!(serde_json::to_string(&B).unwrap(), r#""Black""#);
+        assert_eq!(serde_json::from_str::<Dot>(r#""White""#).unwrap(), W);
+    }
 }
@@ -0,0 +1,22 @@
+//! Library for rendering QR codes to terminals.
+
+extern crate alloc;
+
+pub mod animation;
+pub mod backend;
+pub mod color;
+pub mod compress;
+pub mod console;
+pub mod contrast;
+pub mod core;
+pub mod crc;
+pub mod decode;
+pub mod encode;
+pub mod error;
+pub mod image;
+pub mod payload;
+pub mod render;
+
+pub use crate::error::QrTermError;
+pub use crate::image::{Dot, HalfPoint, Image, Point};
+pub use crate::render::{render_to_string, RenderOptions};
@@ -1,46 +1,3380 @@
-mod image;
-
-use crate::image::Dot;
-use anyhow::{bail, Result};
-use qrcode::QrCode;
-use std::io::{self, Read};
+use anyhow::{anyhow, bail, Result};
+use base64::Engine as _;
+use log::{debug, info, warn, LevelFilter};
+use qrcode::render::Renderer;
+use qrcode::types::Mode;
+use qrcode::{EcLevel, Version};
+use qrterm::animation::{self, GifOptions};
+use qrterm::backend::{Backend, FinderStyle, Iterm2, Kitty, Logo, Pbm, Png, Sixel, Svg};
+use qrterm::color::{ColorDepth, Rgb};
+use qrterm::compress;
+use qrterm::console;
+use qrterm::contrast;
+use qrterm::crc;
+use qrterm::decode;
+use qrterm::encode;
+use qrterm::image::{Charset, Density, Dot, Image};
+use qrterm::payload::{self, Contact, WifiSecurity};
+use qrterm::render::ColorMode;
+use qrterm::{QrTermError, RenderOptions};
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::slice;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
+#[structopt(settings = &[AppSettings::DisableHelpSubcommand, AppSettings::DisableVersion])]
 struct Options {
     /// Data to display in a terminal QR code.
     #[structopt(name = "DATA")]
     data: Vec<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
+    /// Read the data to display from the specified file instead of from DATA
+    /// or standard input.
+    #[structopt(long, parse(from_os_str), conflicts_with = "DATA")]
+    file: Option<PathBuf>,
+
+    /// How the input data is encoded, either "raw" to display it as is, or
+    /// "hex" or "base64" to decode it and display the resulting bytes in byte
+    /// mode. Whitespace in encoded input is ignored.
+    #[structopt(long, default_value = "raw", possible_values = &["raw", "hex", "base64"])]
+    input_encoding: InputEncoding,
+
+    /// When no DATA is given and standard input is an interactive terminal,
+    /// fail if no input is received within the specified number of seconds.
+    #[structopt(long)]
+    timeout: Option<u64>,
+
+    /// Fail if the data is not well-formed UTF-8 text, reporting the byte
+    /// offset of the first invalid sequence. This only applies to raw input,
+    /// as hex and base64 `--input-encoding`s are meant for binary data.
+    #[structopt(long)]
+    strict_utf8: bool,
+
+    /// Compress the data with gzip before encoding it. The compressed payload
+    /// is prefixed with a magic so that decoders know to decompress it.
+    #[structopt(long)]
+    compress: bool,
+
+    /// Append a CRC32 checksum of the payload so that decoders can verify its
+    /// integrity. The checksum is appended after compression.
+    #[structopt(long)]
+    crc: bool,
+
+    /// The output format, one of "terminal", "svg", "png" or "pbm". Formats
+    /// other than "terminal" only support a single QR code.
+    #[structopt(long, default_value = "terminal", possible_values = &["terminal", "svg", "png", "pbm"])]
+    format: Format,
+
+    /// Write the output to the specified file instead of standard output.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Write each input, such as each DATA argument with `--each-arg`, to its
+    /// own file in the specified directory, which is created if missing.
+    #[structopt(long, parse(from_os_str), conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+
+    /// The file name template for `--output-dir`. "{index}" is replaced with
+    /// the position of the input starting from 1, "{content}" with the input
+    /// made safe for file names, and "{ext}" with the extension of the
+    /// `--format`.
+    #[structopt(long, default_value = "code-{index}.{ext}")]
+    name_template: String,
+
+    /// The size of each module in pixels for the "png" and "svg" formats and
+    /// for terminal graphics protocols.
+    #[structopt(long, default_value = "8")]
+    pixels_per_module: u32,
+
+    /// Make light modules transparent in the "png" and "svg" formats, so that
+    /// the code can be placed over another background. Scanners need enough
+    /// contrast against that background to read the code.
+    #[structopt(long)]
+    background_transparent: bool,
+
+    /// How to draw the finder patterns in the corners of the QR code in the
+    /// "svg" format, either "solid" for concentric filled squares or
+    /// "outline" for a square outline around a filled centre. Other formats
+    /// draw every module as a square of pixels or characters, so they only
+    /// support "solid".
+    #[structopt(long, default_value = "solid", possible_values = &["solid", "outline"])]
+    finder_style: FinderStyle,
+
+    /// Overlay the specified image, such as a PNG or JPEG, in the centre of
+    /// the QR code in the "svg" and "png" formats, drawn at its size in
+    /// pixels. The modules under the logo are cleared and the error
+    /// correction level is forced to H so that the code still scans, as long
+    /// as the logo covers no more than about 25% of it.
+    #[structopt(long, parse(from_os_str))]
+    logo: Option<PathBuf>,
+
+    /// How to draw the QR code in the terminal, one of "blocks" for unicode
+    /// block characters, or "sixel", "kitty" or "iterm2" to draw an inline
+    /// image with the terminal's graphics protocol. With "auto", the Kitty,
+    /// iTerm2 and Sixel protocols are detected from the environment, falling
+    /// back to block characters. Images use `--pixels-per-module`.
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "blocks", "sixel", "kitty", "iterm2"])]
+    protocol: Protocol,
+
+    /// The characters to render with, one of "unicode" for block characters,
+    /// or "ascii" to draw each module as two `#` characters or spaces for
+    /// terminals without unicode support. With "auto", ASCII is used when the
+    /// locale from `LC_ALL`, `LC_CTYPE` or `LANG` is not UTF-8. ASCII output
+    /// is never coloured and ignores `--density`.
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "unicode", "ascii"])]
+    charset: CharsetMode,
+
+    /// How densely to pack QR modules into characters, one of "half" for half
+    /// blocks with square modules, "quad" for quadrant blocks with two by two
+    /// modules per character, or "braille" for braille patterns with two by
+    /// four modules per character. Denser output fits larger codes in a
+    /// terminal but may be harder to scan. With "auto", the least dense of
+    /// these whose output fits the terminal width from `--width` or the
+    /// terminal is used, falling back to "half" when standard output is not a
+    /// terminal.
+    #[structopt(long, default_value = "half", possible_values = &["auto", "half", "quad", "braille"])]
+    density: DensityMode,
+
+    /// Draw a compact preview of the QR code with braille patterns, for
+    /// checking that it was generated correctly without it filling the
+    /// screen. The preview may not scan.
+    #[structopt(long)]
+    preview: bool,
+
+    /// When to colour the QR code with ANSI escape sequences, one of "auto",
+    /// "always" or "never". Colours make the code render black on white
+    /// regardless of the terminal's theme; "auto" only enables them when
+    /// standard output is a terminal and `NO_COLOR` is not set, and "always"
+    /// is an error when it is. The colour depth is detected from the `TERM`
+    /// and `COLORTERM` environment variables.
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+    color: ColorMode,
+
+    /// Swap the colours of dark and light modules when colouring the QR code,
+    /// rendering it white on black.
+    #[structopt(long)]
+    invert: bool,
+
+    /// A preset of colours for colouring the QR code, one of "light" for dark
+    /// grey on off-white, "dark" for off-white on dark grey, "solarized" for
+    /// the Solarized base03 on base3 and "high-contrast" for black on white.
+    /// The dark theme is inverted, and `--foreground` and `--background`
+    /// override the colours of dark and light modules set by the theme.
+    #[structopt(long, possible_values = &["light", "dark", "solarized", "high-contrast"])]
+    theme: Option<Theme>,
+
+    /// The colour of dark modules when colouring the QR code, in `#RRGGBB`,
+    /// `#RGB` or `rgb(r, g, b)` notation or a name like "navy". Defaults to
+    /// black.
+    #[structopt(long)]
+    foreground: Option<Rgb>,
+
+    /// The colour of light modules when colouring the QR code. Defaults to
+    /// white.
+    #[structopt(long)]
+    background: Option<Rgb>,
+
+    /// Keep a background colour that does not contrast enough with the
+    /// foreground colour to scan, instead of replacing it with black or white.
+    #[structopt(long)]
+    no_auto_contrast: bool,
+
+    /// Fail unless the WCAG contrast ratio between the requested foreground
+    /// and background colours is at least `--min-contrast`, since low
+    /// contrast codes may not scan. The colours are checked before they are
+    /// adjusted for contrast, in every output mode. Pass `--force` to render
+    /// them anyway.
+    #[structopt(long)]
+    contrast_check: bool,
+
+    /// The minimum contrast ratio for `--contrast-check`, from 1 for no
+    /// contrast to 21 for black on white, which implies `--contrast-check`.
+    /// Defaults to 3.
+    #[structopt(long)]
+    min_contrast: Option<f64>,
+
+    /// The dot used for dark QR modules, either "black" or "white".
+    #[structopt(long, default_value = "black")]
+    dark: Dot,
+
+    /// The dot used for light QR modules, either "black" or "white".
+    #[structopt(long, default_value = "white")]
+    light: Dot,
+
+    /// The width of the light border around the QR code in modules. Scanners
+    /// expect a quiet zone of at least 4 modules, but a smaller one may still
+    /// scan and helps large codes fit on screen.
+    #[structopt(long, default_value = "4")]
+    quiet_zone: u32,
+
+    /// Draw every module of the QR code the specified number of times wider
+    /// and taller, which makes it easier to scan from a distance.
+    #[structopt(long, default_value = "1")]
+    scale: usize,
+
+    /// Horizontally center the QR code in the terminal, failing if it is too
+    /// wide to fit. The terminal width is read from `--width`, the `COLUMNS`
+    /// environment variable or detected from the terminal.
+    #[structopt(long)]
+    center: bool,
+
+    /// The width of the terminal in columns for `--center`, overriding the
+    /// detected width. This makes the layout deterministic when the output is
+    /// piped, for example to a pager.
+    #[structopt(long)]
+    width: Option<usize>,
+
+    /// Mirror the `--center` padding for terminals that lay out text from
+    /// right to left, writing it after each line instead of before it.
+    #[structopt(long, requires = "center")]
+    rtl: bool,
+
+    /// Rotate the QR code clockwise by the specified number of degrees.
+    #[structopt(long, default_value = "0", possible_values = &["0", "90", "180", "270"])]
+    rotate: usize,
+
+    /// Display each DATA argument as its own QR code instead of joining them.
+    #[structopt(long)]
+    each_arg: bool,
+
+    /// Split the data into chunks of N bytes, displaying each one as its own
+    /// QR code labelled with its position in the sequence.
+    #[structopt(long)]
+    chunk: Option<usize>,
+
+    /// Write the QR codes, such as the codes for each chunk, as frames of an
+    /// animated GIF to the specified file instead of displaying them.
+    #[structopt(long, parse(from_os_str))]
+    gif: Option<PathBuf>,
+
+    /// The delay between GIF frames in milliseconds.
+    #[structopt(long, default_value = "1000")]
+    gif_delay: u32,
+
+    /// The number of times to repeat the GIF animation. By default, the
+    /// animation loops forever.
+    #[structopt(long)]
+    gif_loops: Option<u16>,
+
+    /// Split data that is too large for a single QR code across up to 16 QR
+    /// codes using structured append, so that scanners that support it can
+    /// reassemble the data. Each QR code uses at most `--version` or the
+    /// largest version that fits `--max-terminal-modules`.
+    #[structopt(long, conflicts_with = "chunk")]
+    split: bool,
+
+    /// How to lay out the QR codes of a `--split` sequence, either "stack" to
+    /// display them one after the other, or "row" to display them side by
+    /// side.
+    #[structopt(long, default_value = "stack", possible_values = &["stack", "row"])]
+    split_layout: SplitLayout,
+
+    /// Lay out the QR codes of `--each-arg`, `--chunk` or `--split` in grids
+    /// of COLS columns and ROWS rows, such as "3x2", instead of one after the
+    /// other. Codes are padded to the size of the largest one, and codes that
+    /// do not fit in a grid start a new one.
+    #[structopt(long, value_name = "COLSxROWS")]
+    grid: Option<Grid>,
+
+    /// Draw a horizontal rule of the specified character, spanning the widest
+    /// code, between consecutive QR codes instead of a blank line.
+    #[structopt(long)]
+    separator_line: Option<char>,
+
+    /// Trim trailing spaces from each rendered line for cleaner copy and
+    /// paste. The right quiet zone is not needed for scanning. This has no
+    /// effect when the QR code is coloured.
+    #[structopt(long)]
+    trim_trailing_spaces: bool,
+
+    /// Render each dot as two characters on its own line, using full blocks for
+    /// dark dots and full stops for light dots, so that the code can be
+    /// photographed and read back with OCR.
+    #[structopt(long)]
+    print_ascii_qr_for_copy: bool,
+
+    /// Draw the quiet zone with the specified character in the text modes
+    /// that draw each dot with two characters, that is with `--charset ascii`
+    /// and `--print-ascii-qr-for-copy`. Light modules inside the QR code are
+    /// drawn as usual, which helps to debug alignment. Not supported with
+    /// `--from-matrix`, whose border is not known.
+    #[structopt(long)]
+    quiet_zone_char: Option<char>,
+
+    /// Output the QR code as a matrix of `1` and `0` characters for dark and
+    /// light modules respectively, with one line per row of modules.
+    #[structopt(long)]
+    matrix: bool,
+
+    /// Read a matrix in the `--matrix` format from standard input and render
+    /// it instead of encoding DATA.
+    #[structopt(long)]
+    from_matrix: bool,
+
+    /// Output the rendered code on a single line, escaping backslashes as
+    /// `\\` and newlines as `\n`, so that it can be stored in a variable and
+    /// later displayed with `echo -e`.
+    #[structopt(long)]
+    oneline: bool,
+
+    /// Don't raise the error correction level when the selected QR code
+    /// version has spare capacity for it, so that the level is always the one
+    /// passed to `--ec-level`.
+    #[structopt(long)]
+    no_boost_ecc: bool,
+
+    /// The minimum error correction level, one of "L", "M", "Q" or "H". The
+    /// level is raised when the QR code has spare capacity for it unless
+    /// `--no-boost-ecc` is passed.
+    #[structopt(long, default_value = "M", parse(try_from_str = parse_ec_level))]
+    ec_level: EcLevel,
+
+    /// Use exactly the specified QR code version (from 1 to 40), failing if
+    /// the data does not fit.
+    #[structopt(
+        long = "version",
+        value_name = "version",
+        conflicts_with = "min-version"
+    )]
+    qr_version: Option<i16>,
+
+    /// Encode all of the data in the specified mode, one of "numeric",
+    /// "alphanumeric" or "byte", instead of optimally mixing modes.
+    #[structopt(long, parse(try_from_str = parse_mode))]
+    mode: Option<Mode>,
+
+    /// Use at least the specified QR code version (from 1 to 40), even if the
+    /// data would fit in a smaller one.
+    #[structopt(long)]
+    min_version: Option<i16>,
+
+    /// Print how the data was split into encoding mode segments, along with the
+    /// bit cost of each segment, to standard error.
+    #[structopt(long)]
+    encoding_report: bool,
+
+    /// Render the data at each error correction level side by side, labelled
+    /// with their level, for comparing their density.
+    #[structopt(long)]
+    compare_ec: bool,
+
+    /// Refuse to render QR codes that are wider than the specified number of
+    /// modules, excluding the quiet zone, unless `--force` is passed.
+    #[structopt(long)]
+    max_terminal_modules: Option<usize>,
+
+    /// Render QR codes even if they exceed `--max-terminal-modules` or fail
+    /// the `--contrast-check`.
+    #[structopt(long)]
+    force: bool,
+
+    /// Print how much of the QR code's data capacity is used by the payload to
+    /// standard error.
+    #[structopt(long)]
+    utilization: bool,
+
+    /// Scan the rendered QR code and verify that it decodes back to the input,
+    /// printing OK or FAIL to standard error and exiting with an error on
+    /// failure.
+    #[structopt(long)]
+    scan_test: bool,
+
+    /// Log details to standard error. Pass once for a summary of the encoded
+    /// QR code, or twice to log each encoding and rendering step.
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Print nothing but the QR code and fatal errors, suppressing warnings
+    /// and the `--scan-test` result.
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Don't set up the console before rendering. On Windows, this skips
+    /// switching the console to UTF-8 and enabling ANSI escape sequences.
+    #[structopt(long)]
+    no_console_setup: bool,
+
+    /// Print the terminal's reported background colour and the nearest colour
+    /// in the 256 colour palette, then exit.
+    #[structopt(long)]
+    sample_background: bool,
+
+    /// Redraw the QR code in place whenever the data changes, reading a new
+    /// payload from every line of standard input or from the output of the
+    /// `--exec` command. On Unix, the QR code is also redrawn for the new
+    /// terminal width when the terminal is resized.
+    #[structopt(long)]
+    watch: bool,
+
+    /// With `--watch`, run the shell command periodically and display its
+    /// output, without trailing newlines, as the data.
+    #[structopt(long, requires = "watch")]
+    exec: Option<String>,
+
+    /// The number of seconds between runs of the `--exec` command.
+    #[structopt(long, default_value = "30")]
+    interval: u64,
+
+    /// Encode and render the data N times to a sink and print the timing to
+    /// standard error instead of displaying it.
+    #[structopt(long, hidden = true)]
+    repeat: Option<usize>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Display a checkerboard test pattern for diagnosing terminal font and
+    /// glyph alignment issues.
+    #[structopt(name = "testpattern", setting = AppSettings::Hidden)]
+    TestPattern {
+        /// The width of the pattern in dots.
+        #[structopt(long, default_value = "32")]
+        width: usize,
+
+        /// The height of the pattern in dots.
+        #[structopt(long, default_value = "17")]
+        height: usize,
+    },
+
+    /// Scan a QR code from a PNG or JPEG image, such as a photo or screenshot,
+    /// and print its payload. Compressed payloads are decompressed.
+    Decode {
+        /// The image file to scan. Reads the image from standard input when
+        /// omitted.
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Verify and strip the CRC32 checksum appended to the payload with
+        /// `--crc`.
+        #[structopt(long)]
+        crc: bool,
+    },
+
+    /// Display a QR code for joining a WiFi network.
+    Wifi {
+        /// The network name.
+        #[structopt(long)]
+        ssid: String,
+
+        /// The network password. Not allowed with "nopass" security.
+        #[structopt(long)]
+        password: Option<String>,
+
+        /// The network security type, one of "wpa2", "wpa3", "wep" or
+        /// "nopass". Defaults to "wpa2" when a password is given, and
+        /// "nopass" otherwise.
+        #[structopt(long)]
+        security: Option<WifiSecurity>,
+
+        /// Mark the network as hidden, so that devices connect to it even if
+        /// it does not broadcast its SSID.
+        #[structopt(long)]
+        hidden: bool,
+    },
+
+    /// Display a QR code for a vCard contact.
+    Vcard {
+        /// The full name of the contact.
+        #[structopt(long)]
+        name: String,
+
+        /// The email address of the contact.
+        #[structopt(long)]
+        email: Option<String>,
+
+        /// The phone number of the contact.
+        #[structopt(long)]
+        phone: Option<String>,
+
+        /// The organization of the contact.
+        #[structopt(long)]
+        org: Option<String>,
+
+        /// The website of the contact.
+        #[structopt(long)]
+        url: Option<String>,
+    },
+
+    /// Display a QR code for adding a time-based one-time password to an
+    /// authenticator app.
+    Otp {
+        /// The name of the service issuing the one-time password.
+        #[structopt(long)]
+        issuer: String,
+
+        /// The account name, such as a username or email address.
+        #[structopt(long)]
+        account: String,
+
+        /// The base32 encoded secret.
+        #[structopt(long)]
+        secret: String,
+
+        /// The number of digits in each one-time password.
+        #[structopt(long, default_value = "6")]
+        digits: u8,
+
+        /// The number of seconds each one-time password is valid for.
+        #[structopt(long, default_value = "30")]
+        period: u32,
+    },
+
+    /// Display a QR code for composing an email.
+    Mailto {
+        /// The recipient's email address.
+        to: String,
+
+        /// The subject of the email.
+        #[structopt(long)]
+        subject: Option<String>,
+
+        /// The body of the email.
+        #[structopt(long)]
+        body: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
-    let options = Options::from_args();
-    let data = if options.data.is_empty() {
-        let mut buffer = Vec::new();
-        io::stdin().read_to_end(&mut buffer)?;
-        buffer
-    } else {
-        options.data.join(" ").into_bytes()
+    // Rust ignores `SIGPIPE` by default, which turns writing to a closed pipe
+    // (such as `qrterm ... | head`) into an error. Restore the default
+    // behaviour of exiting quietly like other command line tools.
+    #[cfg(unix)]
+    // SAFETY: Resetting the signal handler to its default is done before any
+    // other threads are spawned.
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+
+    match run() {
+        Err(err) if is_broken_pipe(&err) => Ok(()),
+        result => result,
+    }
+}
+
+/// The output format for rendered QR codes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Format {
+    Terminal,
+    Svg,
+    Png,
+    Pbm,
+}
+
+impl Format {
+    /// Returns the file extension for the format.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Terminal => "txt",
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Pbm => "pbm",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "terminal" => Ok(Format::Terminal),
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            "pbm" => Ok(Format::Pbm),
+            _ => bail!("invalid output format '{}'", s),
+        }
+    }
+}
+
+/// How to draw QR codes in the terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Protocol {
+    Auto,
+    Blocks,
+    Sixel,
+    Kitty,
+    Iterm2,
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Protocol::Auto),
+            "blocks" => Ok(Protocol::Blocks),
+            "sixel" => Ok(Protocol::Sixel),
+            "kitty" => Ok(Protocol::Kitty),
+            "iterm2" => Ok(Protocol::Iterm2),
+            _ => bail!("invalid terminal graphics protocol '{}'", s),
+        }
+    }
+}
+
+/// How input data is encoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum InputEncoding {
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "raw" => Ok(InputEncoding::Raw),
+            "hex" => Ok(InputEncoding::Hex),
+            "base64" => Ok(InputEncoding::Base64),
+            _ => bail!("invalid input encoding '{}'", s),
+        }
+    }
+}
+
+/// Which characters to render QR codes with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum CharsetMode {
+    Auto,
+    Unicode,
+    Ascii,
+}
+
+impl FromStr for CharsetMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(CharsetMode::Auto),
+            "unicode" => Ok(CharsetMode::Unicode),
+            "ascii" => Ok(CharsetMode::Ascii),
+            _ => bail!("invalid character set '{}'", s),
+        }
+    }
+}
+
+/// How densely to pack QR modules into characters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DensityMode {
+    Auto,
+    Fixed(Density),
+}
+
+impl FromStr for DensityMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(DensityMode::Auto),
+            _ => parse_density(s).map(DensityMode::Fixed),
+        }
+    }
+}
+
+/// How to lay out the QR codes of a structured append sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SplitLayout {
+    Stack,
+    Row,
+}
+
+impl FromStr for SplitLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stack" => Ok(SplitLayout::Stack),
+            "row" => Ok(SplitLayout::Row),
+            _ => bail!("invalid split layout '{}'", s),
+        }
+    }
+}
+
+/// The number of columns and rows of a `--grid` of QR codes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Grid {
+    columns: usize,
+    rows: usize,
+}
+
+impl FromStr for Grid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (columns, rows) = s
+            .split_once('x')
+            .and_then(|(columns, rows)| Some((columns.parse().ok()?, rows.parse().ok()?)))
+            .filter(|&(columns, rows)| columns > 0 && rows > 0)
+            .ok_or_else(|| anyhow!("invalid grid '{}', expected COLSxROWS", s))?;
+        Ok(Grid { columns, rows })
+    }
+}
+
+/// A preset of colours for colouring QR codes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Theme {
+    Light,
+    Dark,
+    Solarized,
+    HighContrast,
+}
+
+impl Theme {
+    /// Returns the foreground and background colours of the theme, and
+    /// whether they are swapped when rendering.
+    fn colors(self) -> (Rgb, Rgb, bool) {
+        let dark_grey = Rgb(0x1c, 0x1c, 0x1c);
+        let off_white = Rgb(0xf5, 0xf5, 0xf5);
+        match self {
+            Theme::Light => (dark_grey, off_white, false),
+            Theme::Dark => (dark_grey, off_white, true),
+            Theme::Solarized => (Rgb(0x00, 0x2b, 0x36), Rgb(0xfd, 0xf6, 0xe3), false),
+            Theme::HighContrast => (Rgb::BLACK, Rgb::WHITE, false),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            "solarized" => Ok(Theme::Solarized),
+            "high-contrast" => Ok(Theme::HighContrast),
+            _ => bail!("invalid theme '{}'", s),
+        }
+    }
+}
+
+/// Parses an error correction level.
+fn parse_ec_level(s: &str) -> Result<EcLevel> {
+    match s {
+        "L" | "l" => Ok(EcLevel::L),
+        "M" | "m" => Ok(EcLevel::M),
+        "Q" | "q" => Ok(EcLevel::Q),
+        "H" | "h" => Ok(EcLevel::H),
+        _ => bail!("invalid error correction level '{}'", s),
+    }
+}
+
+/// Parses a character density.
+fn parse_density(s: &str) -> Result<Density> {
+    match s {
+        "half" => Ok(Density::Half),
+        "quad" => Ok(Density::Quad),
+        "braille" => Ok(Density::Braille),
+        _ => bail!("invalid density '{}'", s),
+    }
+}
+
+/// Parses an encoding mode.
+fn parse_mode(s: &str) -> Result<Mode> {
+    match s {
+        "numeric" => Ok(Mode::Numeric),
+        "alphanumeric" => Ok(Mode::Alphanumeric),
+        "byte" => Ok(Mode::Byte),
+        _ => bail!("invalid encoding mode '{}'", s),
+    }
+}
+
+/// Returns true if the error was caused by writing to a closed pipe.
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        let io_err = match cause.downcast_ref::<QrTermError>() {
+            Some(QrTermError::Io(err)) => Some(err),
+            _ => cause.downcast_ref::<io::Error>(),
+        };
+        matches!(io_err, Some(err) if err.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Returns the level to log at for the `--verbose` flag occurrences, or to
+/// not log at all with `--quiet`.
+fn log_level(options: &Options) -> LevelFilter {
+    match options.verbose {
+        _ if options.quiet => LevelFilter::Off,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        _ => LevelFilter::Debug,
+    }
+}
+
+fn run() -> Result<()> {
+    let mut options = Options::from_args();
+    env_logger::Builder::new()
+        .filter_level(log_level(&options))
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+    check_contrast(&options)?;
+
+    let payload = match &options.command {
+        Some(Command::TestPattern { width, height }) => {
+            let (dark, light) = dots(&options)?;
+            let image = Image::checkerboard(*width, *height, dark, light);
+            write_image(&options, &image, &mut io::stdout().lock())?;
+            return Ok(());
+        }
+        Some(Command::Decode { file, crc }) => {
+            let bytes = match file {
+                Some(path) => fs::read(path)?,
+                None => {
+                    let mut buffer = Vec::new();
+                    io::stdin().lock().read_to_end(&mut buffer)?;
+                    buffer
+                }
+            };
+            let payload = decode::scan_file(&bytes)?;
+            debug!("decoded {} byte payload", payload.len());
+            let payload = if *crc {
+                crc::verify(&payload)?
+            } else {
+                &payload[..]
+            };
+            let data = if compress::is_compressed(payload) {
+                debug!("decompressing payload");
+                compress::decompress(payload)?
+            } else {
+                payload.to_vec()
+            };
+            io::stdout().lock().write_all(&data)?;
+            return Ok(());
+        }
+        Some(Command::Wifi {
+            ssid,
+            password,
+            security,
+            hidden,
+        }) => {
+            let security = security.unwrap_or(match password {
+                Some(_) => WifiSecurity::Wpa,
+                None => WifiSecurity::None,
+            });
+            Some(payload::wifi(ssid, password.as_deref(), security, *hidden)?)
+        }
+        Some(Command::Vcard {
+            name,
+            email,
+            phone,
+            org,
+            url,
+        }) => Some(payload::vcard(&Contact {
+            name,
+            email: email.as_deref(),
+            phone: phone.as_deref(),
+            org: org.as_deref(),
+            url: url.as_deref(),
+        })),
+        Some(Command::Otp {
+            issuer,
+            account,
+            secret,
+            digits,
+            period,
+        }) => Some(payload::otpauth(issuer, account, secret, *digits, *period)?),
+        Some(Command::Mailto { to, subject, body }) => {
+            Some(payload::mailto(to, subject.as_deref(), body.as_deref()))
+        }
+        None => None,
     };
 
-    if data.is_empty() {
-        bail!("empty data");
+    if options.sample_background {
+        match console::query_background()? {
+            Some(background) => println!(
+                "background: #{:02x}{:02x}{:02x}, nearest 256 colour index {}",
+                background.0,
+                background.1,
+                background.2,
+                background.to_ansi256(),
+            ),
+            None => println!("background: unknown"),
+        }
+        return Ok(());
     }
 
-    // TODO(nlordell): Implement terminal colours.
-    let image = QrCode::new(&data)?.render::<Dot>().build();
-    for line in &image.lines {
-        for point in line {
-            print!("{}", point.to_char());
+    if options.from_matrix {
+        let mut matrix = String::new();
+        io::stdin().lock().read_to_string(&mut matrix)?;
+        let image = parse_matrix(&options, &matrix)?;
+        if !options.no_console_setup {
+            console::setup()?;
         }
-        println!();
+        write_image(&options, &image, &mut io::stdout().lock())?;
+        return Ok(());
     }
-    if let Some(last_line) = &image.last_line {
-        for point in last_line {
-            print!("{}", point.to_char());
+
+    if options.input_encoding != InputEncoding::Raw {
+        match options.mode {
+            None | Some(Mode::Byte) => options.mode = Some(Mode::Byte),
+            Some(_) => bail!("decoded input can only be encoded in byte mode"),
         }
-        println!();
     }
+    if options.scale == 0 {
+        bail!("scale must be positive");
+    }
+    if options.chunk == Some(0) {
+        bail!("chunk size must be positive");
+    }
+    if options.watch {
+        return watch(&mut options);
+    }
+
+    let inputs = if let Some(payload) = payload {
+        debug!("built {} byte payload", payload.len());
+        vec![payload.into_bytes()]
+    } else {
+        resolve_inputs(&options, || {
+            let stdin = io::stdin();
+            match options.timeout {
+                Some(timeout) if stdin.is_terminal() => {
+                    read_with_timeout(stdin, Duration::from_secs(timeout))
+                }
+                _ => {
+                    let mut buffer = Vec::new();
+                    stdin.lock().read_to_end(&mut buffer)?;
+                    Ok(buffer)
+                }
+            }
+        })?
+    };
+
+    let inputs = prepare_inputs(&options, inputs)?;
+    if options.preview {
+        warn!("preview output is drawn with braille patterns and may not scan");
+    }
+
+    if let Some(count) = options.repeat {
+        let elapsed = render_repeatedly(&options, &inputs, count, &mut io::sink())?;
+        eprintln!(
+            "rendered {} times in {:?} ({:?} per render)",
+            count,
+            elapsed,
+            elapsed / count.max(1) as u32,
+        );
+        return Ok(());
+    }
+
+    if let Some(path) = &options.gif {
+        let images = build_codes(&options, &inputs)?
+            .into_iter()
+            .map(|(_, image)| image)
+            .collect::<Vec<_>>();
+        let file = BufWriter::new(File::create(path)?);
+        animation::write_gif(
+            &images,
+            &GifOptions {
+                delay_ms: options.gif_delay,
+                loops: options.gif_loops,
+                ..Default::default()
+            },
+            file,
+        )?;
+        info!("wrote {} frames to {}", images.len(), path.display());
+        return Ok(());
+    }
+
+    if let Some(dir) = &options.output_dir {
+        let paths = write_output_dir(&options, dir, &inputs)?;
+        info!("wrote {} files to {}", paths.len(), dir.display());
+        return Ok(());
+    }
+
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => {
+            if !options.no_console_setup && options.format == Format::Terminal {
+                console::setup()?;
+            }
+            Box::new(io::stdout().lock())
+        }
+    };
+    write_output(&options, &inputs, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
 
+/// Writes the inputs as QR codes in the output format.
+fn write_output(options: &Options, inputs: &[Vec<u8>], mut out: &mut dyn Write) -> Result<()> {
+    let logo = match &options.logo {
+        Some(path) => {
+            if !matches!(options.format, Format::Svg | Format::Png) {
+                bail!("--logo is only supported by the svg and png formats");
+            }
+            Some(Logo::load(&fs::read(path)?)?)
+        }
+        None => None,
+    };
+    if options.finder_style != FinderStyle::Solid && options.format != Format::Svg {
+        bail!("--finder-style outline is only supported by the svg format");
+    }
+    let backend: Option<Box<dyn Backend>> = match options.format {
+        Format::Terminal => None,
+        Format::Svg => Some(Box::new(Svg {
+            scale: options.pixels_per_module,
+            transparent: options.background_transparent,
+            finder_style: options.finder_style,
+            logo: logo.clone(),
+        })),
+        Format::Png => Some(Box::new(Png {
+            scale: options.pixels_per_module,
+            transparent: options.background_transparent,
+            logo: logo.clone(),
+        })),
+        Format::Pbm => Some(Box::new(Pbm)),
+    };
+    if let Some(backend) = backend {
+        if options.background_transparent && options.dark == Dot::White {
+            warn!("dark modules are white, so a transparent background may not scan");
+        }
+        let codes = build_codes(options, inputs)?;
+        let image = match &codes[..] {
+            [(_, image)] => image,
+            _ => bail!("only the terminal format supports multiple QR codes"),
+        };
+        match &logo {
+            Some(logo) => {
+                let image = knock_out_logo(options, image, logo);
+                match inputs {
+                    [data] if options.scan_test && !options.split => {
+                        scan_test(options, &image, data)?
+                    }
+                    _ => {}
+                }
+                backend.write(&image, out)?
+            }
+            None => backend.write(image, out)?,
+        }
+    } else if options.oneline {
+        let mut buffer = Vec::new();
+        render(options, inputs, &mut buffer)?;
+        writeln!(out, "{}", escape_oneline(&String::from_utf8(buffer)?))?;
+    } else {
+        render(options, inputs, &mut out)?;
+    }
     Ok(())
 }
+
+/// Clears the modules of the image covered by the logo, warning when the logo
+/// covers so much of the QR code that it may not scan despite the high error
+/// correction level.
+fn knock_out_logo(options: &Options, image: &Image, logo: &Logo) -> Image {
+    let (_, _, w, h) = logo.covered_dots(image, options.pixels_per_module);
+    let border = options.quiet_zone as usize * options.scale.max(1);
+    let code_width = image.dimensions().0.saturating_sub(2 * border).max(1);
+    let covered = (w * h) as f64 / (code_width * code_width) as f64;
+    if covered > 0.25 {
+        warn!(
+            "logo covers {:.0}% of the QR code, which may not scan; keep it under 25%",
+            covered * 100.0,
+        );
+    }
+    logo.knock_out(image, options.pixels_per_module)
+}
+
+/// Writes each input as a QR code to its own file in the directory, named
+/// after `--name-template`, returning the paths of the written files.
+fn write_output_dir(options: &Options, dir: &Path, inputs: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut paths = Vec::with_capacity(inputs.len());
+    for (i, data) in inputs.iter().enumerate() {
+        let name = options
+            .name_template
+            .replace("{index}", &(i + 1).to_string())
+            .replace("{content}", &file_name_safe(data))
+            .replace("{ext}", options.format.extension());
+        let path = dir.join(name);
+        if paths.contains(&path) {
+            bail!(
+                "--name-template names more than one file {}",
+                path.display()
+            );
+        }
+        let mut file = BufWriter::new(File::create(&path)?);
+        write_output(options, slice::from_ref(data), &mut file)?;
+        file.flush()?;
+        debug!("wrote {}", path.display());
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Makes data safe for use in a file name, replacing everything except ASCII
+/// letters, digits, `-` and `_` with `_` and truncating it to 64 characters.
+fn file_name_safe(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .chars()
+        .take(64)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Renders the inputs `count` times to the writer, returning the total time
+/// taken.
+fn render_repeatedly(
+    options: &Options,
+    inputs: &[Vec<u8>],
+    count: usize,
+    out: &mut impl Write,
+) -> Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..count {
+        render(options, inputs, out)?;
+    }
+    Ok(start.elapsed())
+}
+
+/// Escapes rendered output onto a single line. Only backslashes and newlines
+/// are escaped, which is enough for `echo -e` to reproduce the output exactly.
+/// The final newline is dropped since `echo` adds its own.
+fn escape_oneline(rendered: &str) -> String {
+    rendered
+        .strip_suffix('\n')
+        .unwrap_or(rendered)
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+}
+
+/// Resolves the data to encode from the command line options, returning one
+/// buffer per QR code input.
+///
+/// A `--file` takes precedence, followed by DATA arguments which are either
+/// joined with spaces or, with `--each-arg`, used as separate inputs. When
+/// neither is given, the input is read in full with `read_stdin`, which is
+/// injected so that the resolution is independent of the process' real
+/// standard input.
+fn resolve_inputs<F>(options: &Options, read_stdin: F) -> Result<Vec<Vec<u8>>>
+where
+    F: FnOnce() -> Result<Vec<u8>>,
+{
+    if let Some(path) = &options.file {
+        let buffer = fs::read(path)?;
+        debug!("read {} bytes from {}", buffer.len(), path.display());
+        Ok(vec![buffer])
+    } else if options.data.is_empty() {
+        let buffer = read_stdin()?;
+        debug!("read {} bytes from standard input", buffer.len());
+        Ok(vec![buffer])
+    } else if options.each_arg {
+        debug!("using {} arguments as separate inputs", options.data.len());
+        Ok(options
+            .data
+            .iter()
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect())
+    } else {
+        let data = options.data.join(" ").into_bytes();
+        debug!(
+            "joined {} arguments into {} bytes",
+            options.data.len(),
+            data.len(),
+        );
+        Ok(vec![data])
+    }
+}
+
+/// Validates the inputs and applies the compression and checksum payload
+/// transformations to each of them.
+fn prepare_inputs(options: &Options, inputs: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+    if options.strict_utf8 && options.input_encoding != InputEncoding::Raw {
+        bail!(
+            "--strict-utf8 cannot be used with {:?} encoded input, which is binary",
+            options.input_encoding
+        );
+    }
+    let inputs = inputs
+        .into_iter()
+        .map(|data| decode_input(options.input_encoding, &data))
+        .collect::<Result<Vec<_>>>()?;
+
+    for data in &inputs {
+        if options.strict_utf8 {
+            if let Err(err) = std::str::from_utf8(data) {
+                bail!("invalid UTF-8 at byte offset {}", err.valid_up_to());
+            }
+        }
+        if looks_like_bare_domain(data) {
+            warn!(
+                "data looks like a domain without a scheme, which most scanners \
+                 treat as plain text; prefix it with https:// for a link"
+            );
+        }
+    }
+
+    let inputs = if options.compress {
+        inputs
+            .into_iter()
+            .map(|data| {
+                if data.is_empty() {
+                    return Ok(data);
+                }
+                let compressed = compress::compress(&data)?;
+                debug!("compressed data to {} bytes", compressed.len());
+                Ok(compressed)
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        inputs
+    };
+
+    let inputs = if options.crc {
+        inputs
+            .into_iter()
+            .map(|data| {
+                if data.is_empty() {
+                    data
+                } else {
+                    crc::append(&data)
+                }
+            })
+            .collect()
+    } else {
+        inputs
+    };
+    Ok(inputs)
+}
+
+/// An event that makes `--watch` redraw the QR code.
+#[derive(Debug)]
+enum WatchEvent {
+    /// New data to display.
+    Data(Vec<u8>),
+    /// The terminal was resized.
+    Resize,
+}
+
+/// How often to check whether the terminal was resized while waiting for new
+/// data.
+const RESIZE_POLL: Duration = Duration::from_millis(100);
+
+/// Continuously redraws the QR code in place as the data changes, either by
+/// re-running the `--exec` command every `--interval` seconds or for every
+/// line read from standard input. On Unix, the QR code is also redrawn when
+/// the terminal is resized.
+fn watch(options: &mut Options) -> Result<()> {
+    if !options.no_console_setup {
+        console::setup()?;
+    }
+    console::watch_resize()?;
+
+    let (sender, receiver) = mpsc::channel();
+    match options.exec.clone() {
+        Some(command) => {
+            let interval = Duration::from_secs(options.interval);
+            thread::spawn(move || loop {
+                if sender.send(run_command(&command)).is_err() {
+                    break;
+                }
+                thread::sleep(interval);
+            });
+        }
+        None => {
+            thread::spawn(move || {
+                for event in line_events(io::stdin().lock()) {
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    let events = std::iter::from_fn(|| loop {
+        match receiver.recv_timeout(RESIZE_POLL) {
+            Ok(event) => return Some(event),
+            Err(RecvTimeoutError::Timeout) if console::take_resize() => {
+                return Some(Ok(WatchEvent::Resize))
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    });
+    watch_events(
+        options,
+        events,
+        console::terminal_width,
+        &mut io::stdout().lock(),
+    )
+}
+
+/// Runs the `--exec` shell command, returning its output without trailing
+/// newlines as the data to display.
+fn run_command(command: &str) -> Result<WatchEvent> {
+    let output = if cfg!(windows) {
+        process::Command::new("cmd")
+            .arg("/C")
+            .arg(command)
+            .output()?
+    } else {
+        process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()?
+    };
+    if !output.status.success() {
+        bail!("command '{}' failed with {}", command, output.status);
+    }
+    let mut data = output.stdout;
+    while data.last() == Some(&b'\n') || data.last() == Some(&b'\r') {
+        data.pop();
+    }
+    Ok(WatchEvent::Data(data))
+}
+
+/// Returns a data event for every non-empty line of the input.
+fn line_events(input: impl BufRead) -> impl Iterator<Item = Result<WatchEvent>> {
+    input.lines().filter_map(|line| match line {
+        Ok(line) if line.is_empty() => None,
+        Ok(line) => Some(Ok(WatchEvent::Data(line.into_bytes()))),
+        Err(err) => Some(Err(err.into())),
+    })
+}
+
+/// Redraws the QR code for every event. On a resize, the terminal width is
+/// re-read with the `width` function, unless it was fixed with `--width`, so
+/// that a `--center`ed code is laid out for the new width.
+fn watch_events(
+    options: &mut Options,
+    events: impl IntoIterator<Item = Result<WatchEvent>>,
+    width: impl Fn() -> Option<usize>,
+    out: &mut impl Write,
+) -> Result<()> {
+    let fixed_width = options.width;
+    let mut last = None;
+    for event in events {
+        match event? {
+            WatchEvent::Data(data) => redraw(options, &mut last, data, out)?,
+            WatchEvent::Resize => {
+                if fixed_width.is_none() {
+                    options.width = width();
+                    debug!("terminal resized to {:?} columns", options.width);
+                }
+                if let Some(data) = &last {
+                    draw(options, data, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the previously drawn QR code with one for the data, unless it is
+/// the same as the `last` data that was drawn.
+fn redraw(
+    options: &Options,
+    last: &mut Option<Vec<u8>>,
+    data: Vec<u8>,
+    out: &mut impl Write,
+) -> Result<()> {
+    if last.as_ref() == Some(&data) {
+        return Ok(());
+    }
+    draw(options, &data, out)?;
+    *last = Some(data);
+    Ok(())
+}
+
+/// Replaces the previously drawn QR code with one for the data.
+fn draw(options: &Options, data: &[u8], out: &mut impl Write) -> Result<()> {
+    let inputs = prepare_inputs(options, vec![data.to_vec()])?;
+    // Move the cursor to the top-left corner and clear the screen, so that
+    // the new code replaces the previous one instead of scrolling.
+    write!(out, "\x1b[H\x1b[2J")?;
+    render(options, &inputs, out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Decodes hex or base64 encoded input into the bytes it represents, ignoring
+/// any whitespace.
+fn decode_input(encoding: InputEncoding, data: &[u8]) -> Result<Vec<u8>> {
+    if encoding == InputEncoding::Raw {
+        return Ok(data.to_vec());
+    }
+
+    let text = data
+        .iter()
+        .copied()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+    let decoded = match encoding {
+        InputEncoding::Raw => unreachable!(),
+        InputEncoding::Hex => {
+            if text.len() % 2 != 0 {
+                bail!("invalid hex input: odd number of digits");
+            }
+            // Check the digits up front, as `from_str_radix` accepts a
+            // leading `+` sign.
+            if !text.iter().all(u8::is_ascii_hexdigit) {
+                bail!("invalid hex input: non-hex digit");
+            }
+            text.chunks(2)
+                .map(|pair| {
+                    let pair = std::str::from_utf8(pair).expect("hex digits are ASCII");
+                    u8::from_str_radix(pair, 16).expect("valid hex digits")
+                })
+                .collect()
+        }
+        InputEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(&text)
+            .map_err(|err| anyhow!("invalid base64 input: {}", err))?,
+    };
+    debug!("decoded {} bytes of {:?} input", decoded.len(), encoding);
+    Ok(decoded)
+}
+
+/// Returns true if the data looks like a domain name without a URL scheme,
+/// such as `example.com` or `www.example.org/path`.
+///
+/// This is intentionally conservative in order to avoid false positives on
+/// arbitrary text: the host must consist of at least two dot-separated labels
+/// and end in a common top-level domain.
+fn looks_like_bare_domain(data: &[u8]) -> bool {
+    const COMMON_TLDS: &[&str] = &[
+        "app", "biz", "ca", "ch", "co", "com", "de", "dev", "edu", "eu", "fr", "gov", "info", "io",
+        "it", "jp", "me", "net", "nl", "org", "ru", "uk", "us",
+    ];
+
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.trim(),
+        Err(_) => return false,
+    };
+    if text.contains(char::is_whitespace) || text.contains("://") {
+        return false;
+    }
+
+    let host = text.split('/').next().unwrap_or_default();
+    let labels = host.split('.').collect::<Vec<_>>();
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    labels.len() >= 2
+        && labels.iter().all(valid_label)
+        && labels
+            .last()
+            .map(|tld| COMMON_TLDS.contains(&tld.to_ascii_lowercase().as_str()))
+            .unwrap_or(false)
+}
+
+/// Reads all data from a reader on a background thread, failing if no data
+/// is received within the timeout.
+fn read_with_timeout<R>(mut reader: R, timeout: Duration) -> Result<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(len) => Ok(buffer[..len].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let done = !matches!(&chunk, Ok(chunk) if !chunk.is_empty());
+            if sender.send(chunk).is_err() || done {
+                break;
+            }
+        }
+    });
+
+    let mut data = match receiver.recv_timeout(timeout) {
+        Ok(chunk) => chunk?,
+        Err(RecvTimeoutError::Timeout) => bail!("no data provided; pass DATA or pipe input"),
+        Err(RecvTimeoutError::Disconnected) => return Ok(Vec::new()),
+    };
+    if data.is_empty() {
+        return Ok(data);
+    }
+    for chunk in receiver {
+        let chunk = chunk?;
+        if chunk.is_empty() {
+            break;
+        }
+        data.extend(chunk);
+    }
+    Ok(data)
+}
+
+/// Renders the inputs as QR codes to the specified writer, one after the
+/// other.
+fn render(options: &Options, inputs: &[Vec<u8>], out: &mut impl Write) -> Result<()> {
+    if options.center {
+        let mut buffer = Vec::new();
+        render_codes(options, inputs, &mut buffer)?;
+        return write_centered(options, &String::from_utf8(buffer)?, out);
+    }
+    render_codes(options, inputs, out)
+}
+
+/// Renders the inputs as labelled QR codes, one after the other or in the
+/// `--grid`.
+fn render_codes(options: &Options, inputs: &[Vec<u8>], out: &mut impl Write) -> Result<()> {
+    let codes = build_codes(options, inputs)?;
+    let pages = match options.grid {
+        Some(grid) => layout_grid(grid, codes, dots(options)?.1),
+        None => codes.into_iter().map(|code| vec![code]).collect(),
+    };
+    let width = pages
+        .iter()
+        .flatten()
+        .map(|(_, image)| image.dimensions().0)
+        .max()
+        .unwrap_or(0);
+    for (i, page) in pages.iter().enumerate() {
+        if i > 0 {
+            write_separator(options, width, out)?;
+        }
+        for (label, image) in page {
+            if let Some(label) = label {
+                writeln!(out, "{}", label)?;
+            }
+            write_image(options, image, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lays out the labelled codes in grids, returning the rows of each grid. The
+/// codes are padded with `light` dots to the size of the largest one, and the
+/// codes of each row are stitched side by side under a header of their
+/// labels.
+fn layout_grid(
+    grid: Grid,
+    codes: Vec<(Option<String>, Image)>,
+    light: Dot,
+) -> Vec<Vec<(Option<String>, Image)>> {
+    let size = |image: &Image| {
+        let width = image.dimensions().0;
+        (width, image.to_dots().len().checked_div(width).unwrap_or(0))
+    };
+    let (width, height) = codes
+        .iter()
+        .map(|(_, image)| size(image))
+        .fold((0, 0), |(w, h), (width, height)| {
+            (w.max(width), h.max(height))
+        });
+
+    let rows = codes
+        .chunks(grid.columns)
+        .map(|row| {
+            let cells = row
+                .iter()
+                .map(|(label, image)| {
+                    (
+                        label.clone().unwrap_or_default(),
+                        image.pad(width, height, light),
+                    )
+                })
+                .collect();
+            let (header, image) = concat_labelled(cells);
+            (Some(header).filter(|header| !header.is_empty()), image)
+        })
+        .collect::<Vec<_>>();
+    rows.chunks(grid.rows).map(<[_]>::to_vec).collect()
+}
+
+/// Writes the rendered output horizontally centered in the terminal.
+fn write_centered(options: &Options, rendered: &str, out: &mut impl Write) -> Result<()> {
+    if protocol(options) != Protocol::Blocks {
+        bail!("--center only supports QR codes drawn with characters");
+    }
+    let width = rendered.lines().map(display_width).max().unwrap_or(0);
+    let columns = match terminal_columns(options) {
+        Some(columns) => columns,
+        None => {
+            warn!("could not detect the terminal width, not centering");
+            out.write_all(rendered.as_bytes())?;
+            return Ok(());
+        }
+    };
+    if width > columns {
+        bail!(
+            "QR code is {} columns wide, which does not fit in the {} column \
+             terminal; try a denser --density, a smaller --scale or --quiet-zone, \
+             or less data",
+            width,
+            columns,
+        );
+    }
+
+    let padding = " ".repeat((columns - width) / 2);
+    for line in rendered.lines() {
+        if options.rtl {
+            writeln!(out, "{}{}", line, padding)?;
+        } else {
+            writeln!(out, "{}{}", padding, line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the width of the terminal in columns from `--width`, the `COLUMNS`
+/// environment variable or the terminal itself, in that order.
+fn terminal_columns(options: &Options) -> Option<usize> {
+    options
+        .width
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .or_else(console::terminal_width)
+}
+
+/// Returns the number of characters displayed for a line, ignoring ANSI
+/// escape sequences.
+fn display_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// Encodes and renders the inputs, or each of their chunks, as QR code images
+/// along with their labels.
+fn build_codes(options: &Options, inputs: &[Vec<u8>]) -> Result<Vec<(Option<String>, Image)>> {
+    let build = |data: &[u8], label: Option<String>| -> Result<(Option<String>, Image)> {
+        if options.compare_ec {
+            let (header, image) = build_comparison(options, data)?;
+            let label = match label {
+                Some(label) => format!("{}\n{}", label, header),
+                None => header,
+            };
+            Ok((Some(label), image))
+        } else {
+            Ok((label, build_image(options, data, None)?))
+        }
+    };
+
+    let mut codes = Vec::new();
+    for data in inputs {
+        if options.split {
+            let parts = build_parts(options, data)?;
+            if options.split_layout == SplitLayout::Row {
+                let (header, image) = concat_labelled(parts);
+                codes.push((Some(header), image));
+            } else {
+                codes.extend(parts.into_iter().map(|(label, image)| (Some(label), image)));
+            }
+            continue;
+        }
+        match options.chunk {
+            Some(_) if data.is_empty() => return Err(QrTermError::EmptyData.into()),
+            Some(size) => {
+                let chunks = data.chunks(size).collect::<Vec<_>>();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let label = format!("[{}/{}]", i + 1, chunks.len());
+                    codes.push(build(chunk, Some(label))?);
+                }
+            }
+            None => codes.push(build(data, None)?),
+        }
+    }
+    Ok(codes)
+}
+
+/// Writes a rendered QR code image.
+fn write_image(options: &Options, image: &Image, out: &mut impl Write) -> Result<()> {
+    let scale = options.pixels_per_module;
+    let graphics: Option<Box<dyn Backend>> = match protocol(options) {
+        Protocol::Sixel => Some(Box::new(Sixel { scale })),
+        Protocol::Kitty => Some(Box::new(Kitty { scale })),
+        Protocol::Iterm2 => Some(Box::new(Iterm2 { scale })),
+        Protocol::Auto | Protocol::Blocks => None,
+    };
+
+    if let Some(graphics) = graphics {
+        debug!(
+            "rendering with the {:?} graphics protocol",
+            protocol(options)
+        );
+        graphics.write(image, out)?;
+    } else if options.matrix {
+        debug!("rendering as a matrix of modules");
+        let (columns, _) = image.dimensions();
+        for row in image.to_dots().chunks(columns.max(1)) {
+            let line = row
+                .iter()
+                .map(|&dot| if dot == options.dark { '1' } else { '0' })
+                .collect::<String>();
+            writeln!(out, "{}", line)?;
+        }
+    } else if options.print_ascii_qr_for_copy {
+        debug!("rendering with OCR friendly characters");
+        let mut buffer = Vec::new();
+        image.write_ocr_to(&mut buffer)?;
+        for line in mark_quiet_zone(options, &String::from_utf8(buffer)?).lines() {
+            writeln!(out, "{}", line)?;
+        }
+    } else if charset(options) == Charset::Ascii {
+        debug!("rendering with ASCII characters");
+        let mut buffer = Vec::new();
+        image.write_ascii_to(&mut buffer)?;
+        for line in mark_quiet_zone(options, &String::from_utf8(buffer)?).lines() {
+            if options.trim_trailing_spaces {
+                writeln!(out, "{}", line.trim_end_matches(' '))?;
+            } else {
+                writeln!(out, "{}", line)?;
+            }
+        }
+    } else {
+        let colors = colors(options)?;
+        let density = density(options, image);
+        debug!("rendering with {:?} density characters", density);
+        if colors.is_none() && options.trim_trailing_spaces {
+            let mut buffer = Vec::new();
+            image.write_dense_to(&mut buffer, density, None)?;
+            for line in String::from_utf8(buffer)?.lines() {
+                writeln!(out, "{}", line.trim_end_matches(' '))?;
+            }
+        } else {
+            image.write_dense_to(out, density, colors)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the density to render the image with characters. With auto, this
+/// is the least dense one whose output fits the terminal width, or half
+/// blocks when the width is not known so that piped output does not depend on
+/// the environment.
+fn density(options: &Options, image: &Image) -> Density {
+    if options.preview {
+        return Density::Braille;
+    }
+    if let DensityMode::Fixed(density) = options.density {
+        return density;
+    }
+
+    let detected = options.output.is_none() && io::stdout().is_terminal();
+    let columns = if options.width.is_some() || detected {
+        terminal_columns(options)
+    } else {
+        None
+    };
+    let columns = match columns {
+        Some(columns) => columns,
+        None => return Density::Half,
+    };
+    let (width, _) = image.dimensions();
+    let density = [Density::Half, Density::Quad]
+        .iter()
+        .copied()
+        .find(|density| width.div_ceil(density.cell_size().0) <= columns)
+        .unwrap_or(Density::Braille);
+    debug!(
+        "selected {:?} density for {} dots in {} columns",
+        density, width, columns,
+    );
+    density
+}
+
+/// Replaces the characters of the quiet zone with `--quiet-zone-char` in text
+/// output that has one line per row of dots and two characters per dot.
+fn mark_quiet_zone(options: &Options, text: &str) -> String {
+    let c = match options.quiet_zone_char {
+        Some(c) => c,
+        None => return text.to_owned(),
+    };
+    let border = options.quiet_zone as usize * options.scale.max(1);
+    let rows = text.lines().count();
+    let mut marked = String::with_capacity(text.len());
+    for (row, line) in text.lines().enumerate() {
+        let columns = line.chars().count() / 2;
+        let edge = row < border || row + border >= rows;
+        for (i, glyph) in line.chars().enumerate() {
+            let column = i / 2;
+            if edge || column < border || column + border >= columns {
+                marked.push(c);
+            } else {
+                marked.push(glyph);
+            }
+        }
+        marked.push('\n');
+    }
+    marked
+}
+
+/// Parses a matrix in the `--matrix` format into an image, using the dark and
+/// light dots from the options. The matrix must be square.
+fn parse_matrix(options: &Options, matrix: &str) -> Result<Image> {
+    if options.quiet_zone_char.is_some() {
+        bail!(
+            "--quiet-zone-char cannot be used with --from-matrix, as the matrix has its own border"
+        );
+    }
+    let rows = matrix
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    let size = rows.len();
+    if size == 0 {
+        bail!("matrix is empty");
+    }
+
+    let (dark, light) = dots(options)?;
+    let mut dots = Vec::with_capacity(size * size);
+    for (i, row) in rows.iter().enumerate() {
+        if row.chars().count() != size {
+            bail!(
+                "matrix row {} has {} modules, expected {} for a square matrix",
+                i + 1,
+                row.chars().count(),
+                size,
+            );
+        }
+        for c in row.chars() {
+            dots.push(match c {
+                '1' => dark,
+                '0' => light,
+                _ => bail!("invalid matrix module '{}' in row {}", c, i + 1),
+            });
+        }
+    }
+    Ok(Image::from_dots(&dots, size))
+}
+
+/// Returns the protocol to draw QR codes in the terminal with, detecting it
+/// from the environment when set to auto. Text based output modes always use
+/// block characters.
+fn protocol(options: &Options) -> Protocol {
+    if options.matrix || options.print_ascii_qr_for_copy || options.oneline {
+        return Protocol::Blocks;
+    }
+    if options.protocol != Protocol::Auto {
+        return options.protocol;
+    }
+    if options.output.is_some() || !io::stdout().is_terminal() {
+        return Protocol::Blocks;
+    }
+
+    let protocol = detect_protocol(|name| env::var(name).ok());
+    debug!("detected {:?} terminal graphics protocol", protocol);
+    protocol
+}
+
+/// Terminals that support Sixel graphics but do not advertise it in `TERM`.
+const SIXEL_TERMS: &[&str] = &["foot", "foot-extra", "mlterm", "yaft-256color", "contour"];
+
+/// Detects the terminal graphics protocol from environment variables looked
+/// up with the specified function, preferring Kitty, then iTerm2 and then
+/// Sixel, and falling back to block characters.
+fn detect_protocol(var: impl Fn(&str) -> Option<String>) -> Protocol {
+    let term = var("TERM").unwrap_or_default();
+    if term == "xterm-kitty" || var("KITTY_WINDOW_ID").is_some() {
+        Protocol::Kitty
+    } else if matches!(
+        var("TERM_PROGRAM").as_deref(),
+        Some("iTerm.app" | "WezTerm")
+    ) {
+        Protocol::Iterm2
+    } else if term.contains("sixel") || SIXEL_TERMS.contains(&term.as_str()) {
+        Protocol::Sixel
+    } else {
+        Protocol::Blocks
+    }
+}
+
+/// Returns the character set to render QR codes with, detecting it from the
+/// locale when not specified. Unicode is assumed when no locale is set.
+fn charset(options: &Options) -> Charset {
+    match options.charset {
+        CharsetMode::Unicode => return Charset::Unicode,
+        CharsetMode::Ascii => return Charset::Ascii,
+        CharsetMode::Auto => {}
+    }
+
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            if locale.contains("utf-8") || locale.contains("utf8") {
+                Charset::Unicode
+            } else {
+                debug!("using ASCII characters for non-UTF-8 locale {}", locale);
+                Charset::Ascii
+            }
+        }
+        None => Charset::Unicode,
+    }
+}
+
+/// Returns the foreground and background colours requested with `--theme`,
+/// `--foreground`, `--background` and `--invert`, before they are adjusted
+/// for contrast. Explicit colours override the theme's colours after they
+/// are swapped for an inverted theme.
+fn requested_colors(options: &Options) -> (Rgb, Rgb) {
+    let (foreground, background) = match options.theme.map(Theme::colors) {
+        Some((foreground, background, false)) => (foreground, background),
+        Some((foreground, background, true)) => (background, foreground),
+        None => (Rgb::BLACK, Rgb::WHITE),
+    };
+    let foreground = options.foreground.unwrap_or(foreground);
+    let background = options.background.unwrap_or(background);
+    if options.invert {
+        (background, foreground)
+    } else {
+        (foreground, background)
+    }
+}
+
+/// Fails with `--contrast-check` or `--min-contrast` when the requested
+/// colours do not contrast enough to scan, unless `--force` is passed.
+fn check_contrast(options: &Options) -> Result<()> {
+    if !(options.contrast_check || options.min_contrast.is_some()) || options.force {
+        return Ok(());
+    }
+    let min_contrast = options.min_contrast.unwrap_or(contrast::MIN_CONTRAST);
+    let (foreground, background) = requested_colors(options);
+    let ratio = contrast::contrast_ratio(foreground, background);
+    if ratio < min_contrast {
+        bail!(
+            "the contrast ratio of {:.2}:1 between the foreground colour {:?} \
+             and background colour {:?} is below the minimum of {}:1; pass \
+             --force to render it anyway",
+            ratio,
+            foreground,
+            background,
+            min_contrast,
+        );
+    }
+    Ok(())
+}
+
+/// Returns the foreground and background colours and the colour depth to
+/// render QR codes with, or `None` if they should not be coloured.
+fn colors(options: &Options) -> Result<Option<(Rgb, Rgb, ColorDepth)>> {
+    let depth = match color_depth(options, options.color) {
+        Some(depth) => depth,
+        None => return Ok(None),
+    };
+    let (foreground, background) = requested_colors(options);
+    let (foreground, background) = if options.no_auto_contrast {
+        (foreground, background)
+    } else {
+        let adjusted = contrast::auto_contrast(foreground, background);
+        if adjusted.1 != background {
+            warn!(
+                "replacing background colour {:?} with {:?} for contrast with \
+                 the foreground colour {:?}",
+                background, adjusted.1, foreground,
+            );
+        }
+        adjusted
+    };
+    let render_options = RenderOptions::builder()
+        .foreground(foreground)
+        .background(background)
+        .color(options.color)
+        .no_color(no_color(env::var_os("NO_COLOR").as_deref()))
+        .build()?;
+    Ok(Some((
+        render_options.foreground,
+        render_options.background,
+        depth,
+    )))
+}
+
+/// Returns whether colours are disabled by the value of the `NO_COLOR`
+/// environment variable, which must be set and non-empty following
+/// <https://no-color.org>.
+fn no_color(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|value| !value.is_empty())
+}
+
+/// Returns the colour depth to render QR codes with in the colour mode, or
+/// `None` if they should not be coloured.
+fn color_depth(options: &Options, color: ColorMode) -> Option<ColorDepth> {
+    let enabled = match color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            options.output.is_none()
+                && io::stdout().is_terminal()
+                && !no_color(env::var_os("NO_COLOR").as_deref())
+                && env::var("TERM").map(|term| term != "dumb").unwrap_or(true)
+        }
+    };
+    if !enabled {
+        return None;
+    }
+
+    let depth = ColorDepth::detect(
+        env::var("TERM").ok().as_deref(),
+        env::var("COLORTERM").ok().as_deref(),
+    );
+    debug!("rendering with {:?} colours", depth);
+    Some(depth)
+}
+
+/// Writes the separator between two consecutive QR codes.
+fn write_separator(options: &Options, width: usize, out: &mut impl Write) -> Result<()> {
+    if let Some(c) = options.separator_line {
+        writeln!(out, "{}", c.to_string().repeat(width))?;
+    } else {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Encodes and renders the data at every error correction level, placing the
+/// codes side by side. Returns the combined image along with a header line
+/// labelling each code with its level.
+fn build_comparison(options: &Options, data: &[u8]) -> Result<(String, Image)> {
+    let mut codes = Vec::new();
+    for &(name, ec_level) in &[
+        ("L", EcLevel::L),
+        ("M", EcLevel::M),
+        ("Q", EcLevel::Q),
+        ("H", EcLevel::H),
+    ] {
+        codes.push((name.to_owned(), build_image(options, data, Some(ec_level))?));
+    }
+    Ok(concat_labelled(codes))
+}
+
+/// Returns the dots for dark and light modules, which must differ for the QR
+/// code to be visible at all.
+fn dots(options: &Options) -> Result<(Dot, Dot)> {
+    if options.dark == options.light {
+        return Err(QrTermError::InvalidOptions(format!(
+            "--dark and --light must be different dots, but both are {:?}",
+            options.dark,
+        ))
+        .into());
+    }
+    Ok((options.dark, options.light))
+}
+
+/// Splits the data across a structured append sequence of QR codes, returning
+/// the rendered image of each part labelled with its position.
+fn build_parts(options: &Options, data: &[u8]) -> Result<Vec<(String, Image)>> {
+    let max_version = match options.max_terminal_modules {
+        Some(max) => ((max.saturating_sub(17) / 4).min(40) as i16).max(1),
+        None => 40,
+    };
+    let encode_options = RenderOptions::builder()
+        .ec_level(options.ec_level)
+        .boost_ecc(!options.no_boost_ecc)
+        .min_version(options.min_version)
+        .version(options.qr_version)
+        .mode(options.mode)
+        .quiet_zone(i32::try_from(options.quiet_zone)?)
+        .build()?;
+
+    let (dark, light) = dots(options)?;
+    let parts = encode::split(data, &encode_options.encode, max_version)?;
+    if options.scan_test {
+        warn!("skipping scan test as structured append codes cannot be decoded");
+    }
+    let count = parts.len();
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let image = Renderer::<Dot>::new(&part.colors, part.width(), encode_options.quiet_zone)
+                .dark_color(dark)
+                .light_color(light)
+                .build();
+            let image = transform(options, image);
+            info!(
+                "encoded part {} of {} with {} bytes as version {:?} with error \
+                 correction level {:?}",
+                i + 1,
+                count,
+                part.len,
+                part.version,
+                part.ec_level,
+            );
+            (format!("[{}/{}]", i + 1, count), image)
+        })
+        .collect())
+}
+
+/// Places the images side by side, returning the combined image along with a
+/// header line centering each label above its image.
+fn concat_labelled(codes: Vec<(String, Image)>) -> (String, Image) {
+    const GAP: usize = 2;
+
+    let mut header = String::new();
+    let mut combined: Option<Image> = None;
+    for (label, image) in codes {
+        let (columns, _) = image.dimensions();
+        combined = Some(match combined {
+            Some(combined) => {
+                header.push_str(&" ".repeat(GAP));
+                combined.concat_horizontal(&image, GAP)
+            }
+            None => image,
+        });
+        header.push_str(&format!("{:^width$}", label, width = columns));
+    }
+
+    (
+        header.trim_end().to_owned(),
+        combined.unwrap_or_else(|| Image::from_dots(&[], 0)),
+    )
+}
+
+/// Rotates and scales the image by the `--rotate` and `--scale` options.
+fn transform(options: &Options, image: Image) -> Image {
+    let image = if options.rotate != 0 {
+        debug!("rotating by {} degrees", options.rotate);
+        image.rotate(options.rotate / 90)
+    } else {
+        image
+    };
+    if options.scale > 1 {
+        debug!("scaling by {}", options.scale);
+        image.scale(options.scale)
+    } else {
+        image
+    }
+}
+
+/// Encodes and renders the data as a QR code image. When an error correction
+/// level is specified, it is used as is instead of the default boosted level.
+fn build_image(options: &Options, data: &[u8], ec_level: Option<EcLevel>) -> Result<Image> {
+    let ec_level = match ec_level {
+        None if options.logo.is_some() => Some(EcLevel::H),
+        ec_level => ec_level,
+    };
+    let render_options = RenderOptions::builder()
+        .ec_level(ec_level.unwrap_or(options.ec_level))
+        .boost_ecc(ec_level.is_none() && !options.no_boost_ecc)
+        .min_version(options.min_version)
+        .version(options.qr_version)
+        .mode(options.mode)
+        .quiet_zone(i32::try_from(options.quiet_zone)?)
+        .build()?;
+    let (dark, light) = dots(options)?;
+    let code = encode::encode(data, &render_options.encode)?;
+    match options.max_terminal_modules {
+        Some(max) if code.width() > max && !options.force => bail!(
+            "QR code version {:?} is {} modules wide, which exceeds the maximum \
+             of {}; pass --force to render it anyway",
+            code.version(),
+            code.width(),
+            max,
+        ),
+        _ => {}
+    }
+    if options.encoding_report {
+        write_encoding_report(options, data, code.version(), &mut io::stderr())?;
+    }
+    if options.utilization {
+        let (used, capacity) = encode::utilization(
+            data,
+            code.version(),
+            code.error_correction_level(),
+            options.mode,
+        )?;
+        eprintln!(
+            "{}/{} bytes, {}%",
+            used.div_ceil(8),
+            capacity / 8,
+            used * 100 / capacity,
+        );
+    }
+
+    let image = Renderer::<Dot>::new(&code.to_colors(), code.width(), render_options.quiet_zone)
+        .dark_color(dark)
+        .light_color(light)
+        .build();
+    let image = transform(options, image);
+    let (columns, rows) = image.dimensions();
+    info!(
+        "encoded {} bytes as version {:?} with error correction level {:?} \
+         into {}x{} characters",
+        data.len(),
+        code.version(),
+        code.error_correction_level(),
+        columns,
+        rows,
+    );
+
+    // A logo is only knocked out of the image when it is written, so the scan
+    // test is left to `write_output` for the final image.
+    if options.scan_test && options.logo.is_none() {
+        scan_test(options, &image, data)?;
+    }
+
+    Ok(image)
+}
+
+/// Scans the image and verifies that it decodes back to the data, printing
+/// the result to standard error.
+fn scan_test(options: &Options, image: &Image, data: &[u8]) -> Result<()> {
+    if decode::scan(image, dots(options)?.0).ok().as_deref() == Some(data) {
+        if !options.quiet {
+            eprintln!("scan test: OK");
+        }
+        Ok(())
+    } else {
+        if !options.quiet {
+            eprintln!("scan test: FAIL");
+        }
+        bail!("scanned QR code does not match the input");
+    }
+}
+
+/// Writes how the data is split into encoding mode segments for the version,
+/// along with the bit cost of each segment, one segment per line.
+fn write_encoding_report(
+    options: &Options,
+    data: &[u8],
+    version: Version,
+    out: &mut impl Write,
+) -> Result<()> {
+    for (segment, bits) in encode::segments(data, version, options.mode) {
+        writeln!(
+            out,
+            "{}[{}..{}]: {} bits",
+            format!("{:?}", segment.mode).to_lowercase(),
+            segment.begin,
+            segment.end,
+            bits,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses command line options, with the output forced to uncoloured
+    /// unicode block characters so that it does not depend on the terminal.
+    fn options(args: &[&str]) -> Options {
+        let defaults = [
+            "qrterm",
+            "--color",
+            "never",
+            "--charset",
+            "unicode",
+            "--protocol",
+            "blocks",
+        ];
+        Options::from_iter(defaults.iter().chain(args))
+    }
+
+    /// A writer that counts the number of bytes written to it.
+    #[derive(Default)]
+    struct CountingWriter(usize);
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns the debug messages logged so far by the current test's thread,
+    /// installing a logger that captures them on first use.
+    fn logs() -> Vec<String> {
+        use log::{Log, Metadata, Record};
+        use std::sync::{Mutex, Once};
+        use std::thread::ThreadId;
+
+        static MESSAGES: Mutex<Vec<(ThreadId, String)>> = Mutex::new(Vec::new());
+        static INIT: Once = Once::new();
+
+        struct CaptureLogger;
+
+        impl Log for CaptureLogger {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                MESSAGES
+                    .lock()
+                    .unwrap()
+                    .push((thread::current().id(), record.args().to_string()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        INIT.call_once(|| {
+            log::set_logger(&CaptureLogger).unwrap();
+            log::set_max_level(LevelFilter::Debug);
+        });
+        let current = thread::current().id();
+        MESSAGES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == current)
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn repeat_renders_count_times() {
+        let options = options(&["--repeat", "3"]);
+        let inputs = [b"hello".to_vec()];
+
+        let mut once = CountingWriter::default();
+        render(&options, &inputs, &mut once).unwrap();
+        let mut repeated = CountingWriter::default();
+        render_repeatedly(&options, &inputs, 3, &mut repeated).unwrap();
+
+        assert!(once.0 > 0);
+        assert_eq!(repeated.0, once.0 * 3);
+    }
+
+    #[test]
+    fn chunk_splits_into_labelled_codes() {
+        let options = options(&["--chunk", "4"]);
+        let codes = build_codes(&options, &[b"0123456789".to_vec()]).unwrap();
+        let labels = codes
+            .iter()
+            .map(|(label, _)| label.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, [Some("[1/3]"), Some("[2/3]"), Some("[3/3]")]);
+    }
+
+    #[test]
+    fn debug_logs_segments_and_mask() {
+        logs();
+        build_image(&options(&[]), b"0123456789hello", None).unwrap();
+        let logs = logs();
+        assert!(logs
+            .iter()
+            .any(|log| log.starts_with("segment Numeric[0..10]")));
+        assert!(logs
+            .iter()
+            .any(|log| log.starts_with("segment Byte[10..15]")));
+        assert!(logs
+            .iter()
+            .any(|log| log.starts_with("applied mask pattern")));
+    }
+
+    #[test]
+    fn log_level_for_verbosity() {
+        assert_eq!(log_level(&options(&[])), LevelFilter::Warn);
+        assert_eq!(log_level(&options(&["-v"])), LevelFilter::Info);
+        assert_eq!(log_level(&options(&["-vv"])), LevelFilter::Debug);
+        assert_eq!(log_level(&options(&["-vvv"])), LevelFilter::Debug);
+        assert_eq!(log_level(&options(&["--quiet"])), LevelFilter::Off);
+        assert_eq!(log_level(&options(&["-q"])), LevelFilter::Off);
+
+        let args = ["qrterm", "--quiet", "--verbose", "hello"];
+        assert!(Options::from_iter_safe(&args).is_err());
+    }
+
+    #[test]
+    fn debug_logs_render_mode() {
+        logs();
+        let image = build_image(&options(&[]), b"hello", None).unwrap();
+        for (args, message) in [
+            (&[][..], "rendering with Half density characters"),
+            (
+                &["--print-ascii-qr-for-copy"],
+                "rendering with OCR friendly characters",
+            ),
+            (&["--matrix"], "rendering as a matrix of modules"),
+        ] {
+            write_image(&options(args), &image, &mut io::sink()).unwrap();
+            assert_eq!(logs().last().map(String::as_str), Some(message));
+        }
+    }
+
+    #[test]
+    fn auto_density_fits_terminal_width() {
+        // "hello" is 29 dots wide with the default quiet zone.
+        let image = build_image(&options(&[]), b"hello", None).unwrap();
+        let auto =
+            |args: &[&str]| density(&options(&[&["--density", "auto"], args].concat()), &image);
+        assert_eq!(auto(&["--width", "80"]), Density::Half);
+        assert_eq!(auto(&["--width", "29"]), Density::Half);
+        assert_eq!(auto(&["--width", "20"]), Density::Quad);
+        assert_eq!(auto(&["--width", "15"]), Density::Quad);
+        assert_eq!(auto(&["--width", "10"]), Density::Braille);
+
+        // Without a width, output that is not a terminal falls back to half
+        // blocks.
+        assert_eq!(auto(&["--output", "qr.txt"]), Density::Half);
+        let args = ["qrterm", "--density", "quad"];
+        assert_eq!(density(&Options::from_iter(&args), &image), Density::Quad);
+    }
+
+    #[test]
+    fn preview_is_smaller_than_half_blocks() {
+        let rendered = |args: &[&str]| {
+            let mut out = Vec::new();
+            render(&options(args), &[b"hello".to_vec()], &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let half = rendered(&[]);
+        let preview = rendered(&["--preview"]);
+        assert_eq!(preview, rendered(&["--density", "braille"]));
+
+        let size = |output: &str| {
+            let width = output.lines().map(|line| line.chars().count()).max();
+            (width.unwrap(), output.lines().count())
+        };
+        let ((half_width, half_lines), (preview_width, preview_lines)) =
+            (size(&half), size(&preview));
+        assert!(preview_width < half_width);
+        assert!(preview_lines < half_lines);
+    }
+
+    #[test]
+    fn quiet_zone_char_marks_border() {
+        let image = build_image(&options(&[]), b"hello", None).unwrap();
+        let ocr = |args: &[&str]| {
+            let mut out = Vec::new();
+            let args = [&["--print-ascii-qr-for-copy"], args].concat();
+            write_image(&options(&args), &image, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let plain = ocr(&[]);
+        let marked = ocr(&["--quiet-zone-char", "+"]);
+
+        // "hello" is a 21 module code with a 4 module quiet zone.
+        let lines = marked.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 29);
+        for (i, line) in lines.iter().enumerate() {
+            let chars = line.chars().collect::<Vec<_>>();
+            if (4..25).contains(&i) {
+                assert_eq!(chars[..8], ['+'; 8]);
+                assert_eq!(chars[50..], ['+'; 8]);
+                let inner = chars[8..50].iter().collect::<String>();
+                let expected = plain.lines().nth(i).unwrap().chars().skip(8).take(42);
+                assert_eq!(inner, expected.collect::<String>());
+            } else {
+                assert!(chars.iter().all(|&c| c == '+'));
+            }
+        }
+        // Light modules inside the code keep their usual character.
+        assert!(lines[11].contains(".."));
+
+        let ascii = |args: &[&str]| {
+            let mut out = Vec::new();
+            let args = [&["--charset", "ascii"][..], args].concat();
+            let args = ["qrterm", "--color", "never", "--protocol", "blocks"]
+                .iter()
+                .chain(&args);
+            write_image(&Options::from_iter(args), &image, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let marked = ascii(&["--quiet-zone-char", "."]);
+        assert!(marked.lines().next().unwrap().chars().all(|c| c == '.'));
+        assert!(marked.lines().nth(4).unwrap().starts_with("........##"));
+        assert!(ascii(&[]).lines().next().unwrap().chars().all(|c| c == ' '));
+    }
+
+    #[test]
+    fn separator_line_spans_widest_code() {
+        let options = options(&["--separator-line", "="]);
+        let inputs = [b"a".to_vec(), vec![b'a'; 100], b"b".to_vec()];
+        let mut out = Vec::new();
+        render(&options, &inputs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let widest = out.lines().map(|line| line.chars().count()).max().unwrap();
+        let rules = out
+            .lines()
+            .filter(|line| line.starts_with('='))
+            .collect::<Vec<_>>();
+        assert_eq!(rules.len(), 2);
+        for rule in rules {
+            assert_eq!(rule, "=".repeat(widest));
+        }
+        assert!(widest > 29);
+    }
+
+    #[test]
+    fn scan_test_passes_for_inverted_dots() {
+        let options = options(&["--scan-test", "--dark", "white", "--light", "black"]);
+        build_image(&options, b"hello", None).unwrap();
+    }
+
+    #[test]
+    fn dark_and_light_dots() {
+        let normal = build_image(&options(&[]), b"hello", None).unwrap();
+        let inverted = build_image(
+            &options(&["--dark", "white", "--light", "black"]),
+            b"hello",
+            None,
+        )
+        .unwrap();
+        let flipped = normal
+            .to_dots()
+            .iter()
+            .map(|&dot| {
+                if dot == Dot::Black {
+                    Dot::White
+                } else {
+                    Dot::Black
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(inverted.to_dots(), flipped);
+
+        for dot in ["black", "white"] {
+            let err = build_image(&options(&["--dark", dot, "--light", dot]), b"hello", None)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<QrTermError>(),
+                Some(QrTermError::InvalidOptions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn each_arg_produces_separate_codes() {
+        let read_stdin = || -> Result<Vec<u8>> { panic!("unexpected stdin read") };
+
+        let each_arg = options(&["--each-arg", "a", "b", "c"]);
+        let inputs = resolve_inputs(&each_arg, read_stdin).unwrap();
+        assert_eq!(inputs, [b"a", b"b", b"c"]);
+        assert_eq!(build_codes(&each_arg, &inputs).unwrap().len(), 3);
+
+        let joined = options(&["a", "b", "c"]);
+        let inputs = resolve_inputs(&joined, read_stdin).unwrap();
+        assert_eq!(inputs, [b"a b c"]);
+        assert_eq!(build_codes(&joined, &inputs).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn trim_trailing_spaces_only_without_colour() {
+        let render_with = |options: &Options| {
+            let image = build_image(options, b"hello", None).unwrap();
+            let mut out = Vec::new();
+            write_image(options, &image, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let plain = render_with(&options(&[]));
+        let trimmed = render_with(&options(&["--trim-trailing-spaces"]));
+        assert!(plain.lines().any(|line| line.ends_with(' ')));
+        assert!(!trimmed.lines().any(|line| line.ends_with(' ')));
+        for (plain, trimmed) in plain.lines().zip(trimmed.lines()) {
+            assert_eq!(plain.trim_end_matches(' '), trimmed);
+        }
+
+        let coloured = |extra: &[&str]| {
+            let args = ["qrterm", "--color", "always", "--charset", "unicode"];
+            Options::from_iter(args.iter().chain(&["--protocol", "blocks"]).chain(extra))
+        };
+        let plain = render_with(&coloured(&[]));
+        let trimmed = render_with(&coloured(&["--trim-trailing-spaces"]));
+        assert!(plain.contains('\x1b'));
+        assert_eq!(plain, trimmed);
+    }
+
+    #[test]
+    fn read_with_timeout_reads_all_data() {
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+        let read = read_with_timeout(io::Cursor::new(data.clone()), Duration::from_secs(10));
+        assert_eq!(read.unwrap(), data);
+
+        let empty = read_with_timeout(io::empty(), Duration::from_secs(10));
+        assert_eq!(empty.unwrap(), b"");
+    }
+
+    #[test]
+    fn read_with_timeout_fails_on_blocking_reader() {
+        /// A reader that blocks until its sender is dropped.
+        struct BlockingReader(mpsc::Receiver<()>);
+
+        impl Read for BlockingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                let _ = self.0.recv();
+                Ok(0)
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let read = read_with_timeout(BlockingReader(receiver), Duration::from_millis(50));
+        let err = read.unwrap_err().to_string();
+        assert_eq!(err, "no data provided; pass DATA or pipe input");
+        drop(sender);
+    }
+
+    #[test]
+    fn bare_domain_heuristic() {
+        for data in ["example.com", "www.example.org/path", " Example.IO\n"] {
+            assert!(looks_like_bare_domain(data.as_bytes()), "{}", data);
+        }
+        for data in [
+            "hello world",
+            "https://example.com",
+            "example",
+            "example.invalid",
+            "-example.com",
+            "example..com",
+            "1.5",
+        ] {
+            assert!(!looks_like_bare_domain(data.as_bytes()), "{}", data);
+        }
+        assert!(!looks_like_bare_domain(b"\xffexample.com"));
+    }
+
+    #[test]
+    fn output_dir_writes_file_per_input() {
+        let dir = env::temp_dir().join(format!("qrterm-test-dir-{}", std::process::id()));
+        let dir_arg = dir.to_str().unwrap();
+        let inputs = [b"one".to_vec(), b"two".to_vec(), b"a/b c".to_vec()];
+
+        let png = options(&["--output-dir", dir_arg, "--format", "png"]);
+        let paths = write_output_dir(&png, &dir, &inputs).unwrap();
+        let names = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["code-1.png", "code-2.png", "code-3.png"]);
+        for (path, data) in paths.iter().zip(&inputs) {
+            assert_eq!(&decode::scan_file(&fs::read(path).unwrap()).unwrap(), data);
+        }
+
+        let content = options(&[
+            "--output-dir",
+            dir_arg,
+            "--name-template",
+            "{content}.{ext}",
+        ]);
+        let paths = write_output_dir(&content, &dir, &inputs).unwrap();
+        assert_eq!(paths[2], dir.join("a_b_c.txt"));
+        let mut rendered = Vec::new();
+        render(&content, &inputs[..1], &mut rendered).unwrap();
+        assert_eq!(fs::read(&paths[0]).unwrap(), rendered);
+
+        let duplicate = options(&["--output-dir", dir_arg, "--name-template", "code.txt"]);
+        assert!(write_output_dir(&duplicate, &dir, &inputs).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logo_forces_high_ec_and_clears_centre() {
+        let path = env::temp_dir().join(format!("qrterm-test-logo-{}.png", std::process::id()));
+        let logo = ::image::RgbaImage::from_pixel(40, 40, ::image::Rgba([255, 0, 0, 255]));
+        logo.save(&path).unwrap();
+        let options = options(&["--logo", path.to_str().unwrap(), "--format", "png"]);
+        let logo = Logo::load(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        logs();
+        let image = build_image(&options, b"https://example.com/logo", None).unwrap();
+        assert!(logs()
+            .iter()
+            .any(|log| log.contains("error correction level H")));
+
+        // A 40 pixel logo covers 5 modules of 8 pixels, centred in the 37
+        // dot wide version 3 code including its quiet zone.
+        assert_eq!(image.dimensions().0, 37);
+        assert_eq!(logo.covered_dots(&image, 8), (16, 16, 5, 5));
+        let cleared = knock_out_logo(&options, &image, &logo);
+        assert!(cleared
+            .crop(16, 16, 5, 5)
+            .to_dots()
+            .iter()
+            .all(|&dot| dot == Dot::White));
+        assert_ne!(cleared, image);
+        assert_eq!(
+            decode::scan(&cleared, Dot::Black).unwrap(),
+            b"https://example.com/logo"
+        );
+    }
+
+    #[test]
+    fn scan_test_checks_the_code_with_its_logo() {
+        let path = env::temp_dir().join(format!("qrterm-test-scan-{}.png", std::process::id()));
+        let args = |size: u32| {
+            let logo = ::image::RgbaImage::from_pixel(size, size, ::image::Rgba([255, 0, 0, 255]));
+            logo.save(&path).unwrap();
+            options(&[
+                "--logo",
+                path.to_str().unwrap(),
+                "--format",
+                "png",
+                "--scan-test",
+            ])
+        };
+        let inputs = [b"https://example.com/logo".to_vec()];
+
+        write_output(&args(40), &inputs, &mut Vec::new()).unwrap();
+        // The code without its logo still scans, but the knocked out one does
+        // not once the logo covers most of it.
+        let covered = args(200);
+        assert!(build_image(&covered, &inputs[0], None).is_ok());
+        assert!(write_output(&covered, &inputs, &mut Vec::new()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resolve_inputs_precedence() {
+        let read_stdin = || -> Result<Vec<u8>> { Ok(b"stdin".to_vec()) };
+        let no_stdin = || -> Result<Vec<u8>> { panic!("unexpected stdin read") };
+
+        let path = env::temp_dir().join(format!("qrterm-test-{}", std::process::id()));
+        fs::write(&path, b"file").unwrap();
+        let path = path.to_str().unwrap();
+
+        let file = options(&["--file", path]);
+        assert_eq!(resolve_inputs(&file, no_stdin).unwrap(), [b"file"]);
+        let file_each_arg = options(&["--file", path, "--each-arg"]);
+        assert_eq!(resolve_inputs(&file_each_arg, no_stdin).unwrap(), [b"file"]);
+        fs::remove_file(path).unwrap();
+
+        let data = options(&["a", "b"]);
+        assert_eq!(resolve_inputs(&data, no_stdin).unwrap(), [b"a b"]);
+        let each_arg = options(&["--each-arg", "a", "b"]);
+        assert_eq!(resolve_inputs(&each_arg, no_stdin).unwrap(), [b"a", b"b"]);
+
+        let nothing = options(&[]);
+        assert_eq!(resolve_inputs(&nothing, read_stdin).unwrap(), [b"stdin"]);
+        let nothing_each_arg = options(&["--each-arg"]);
+        assert_eq!(
+            resolve_inputs(&nothing_each_arg, read_stdin).unwrap(),
+            [b"stdin"],
+        );
+
+        let args = ["qrterm", "--file", path, "a"];
+        assert!(Options::from_iter_safe(&args).is_err());
+    }
+
+    #[test]
+    fn oneline_unescapes_to_rendered_output() {
+        /// Unescapes a line like `echo -e`, which adds the final newline.
+        fn unescape(line: &str) -> String {
+            let mut unescaped = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some(c) => unescaped.push(c),
+                    None => unescaped.push('\\'),
+                }
+            }
+            unescaped + "\n"
+        }
+
+        assert_eq!(escape_oneline("a\\b\nc\n"), "a\\\\b\\nc");
+        assert_eq!(unescape(&escape_oneline("a\\b\nc\n")), "a\\b\nc\n");
+
+        let mut rendered = Vec::new();
+        render(&options(&[]), &[b"hello".to_vec()], &mut rendered).unwrap();
+        let rendered = String::from_utf8(rendered).unwrap();
+        let line = escape_oneline(&rendered);
+        assert!(!line.contains('\n'));
+        assert_eq!(unescape(&line), rendered);
+    }
+
+    #[test]
+    fn colors_follow_color_mode_and_invert() {
+        assert_eq!(colors(&options(&[])).unwrap(), None);
+        let args = ["qrterm", "--color", "always", "--invert"];
+        let (foreground, background, _) = colors(&Options::from_iter(&args)).unwrap().unwrap();
+        assert_eq!((foreground, background), (Rgb::WHITE, Rgb::BLACK));
+    }
+
+    #[test]
+    fn no_color_requires_a_value() {
+        assert!(no_color(Some(OsStr::new("1"))));
+        assert!(!no_color(Some(OsStr::new(""))));
+        assert!(!no_color(None));
+    }
+
+    #[test]
+    fn colors_are_only_validated_when_enabled() {
+        let colors = |color: &str| {
+            let args = [
+                "qrterm",
+                "--color",
+                color,
+                "--foreground",
+                "white",
+                "--background",
+                "white",
+                "--no-auto-contrast",
+            ];
+            colors(&Options::from_iter(&args))
+        };
+        assert!(colors("never").unwrap().is_none());
+        assert!(colors("always").is_err());
+    }
+
+    #[test]
+    fn themes_set_colors() {
+        let escapes = |extra: &[&str]| {
+            let args = ["qrterm", "--color", "always"];
+            let (foreground, background, _) = colors(&Options::from_iter(args.iter().chain(extra)))
+                .unwrap()
+                .unwrap();
+            (foreground.fg(), background.bg())
+        };
+        let theme = |theme: &str| escapes(&["--theme", theme]);
+
+        for &(name, foreground, background) in &[
+            ("light", "\x1b[38;2;28;28;28m", "\x1b[48;2;245;245;245m"),
+            ("dark", "\x1b[38;2;245;245;245m", "\x1b[48;2;28;28;28m"),
+            ("solarized", "\x1b[38;2;0;43;54m", "\x1b[48;2;253;246;227m"),
+            (
+                "high-contrast",
+                "\x1b[38;2;0;0;0m",
+                "\x1b[48;2;255;255;255m",
+            ),
+        ] {
+            assert_eq!(
+                theme(name),
+                (foreground.to_owned(), background.to_owned()),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(theme("high-contrast"), escapes(&[]));
+        assert!(Options::from_iter_safe(["qrterm", "--theme", "sepia"]).is_err());
+    }
+
+    #[test]
+    fn colors_override_theme() {
+        let colored = |extra: &[&str]| {
+            let args = ["qrterm", "--color", "always", "--theme", "solarized"];
+            colors(&Options::from_iter(args.iter().chain(extra)))
+                .unwrap()
+                .map(|(foreground, background, _)| (foreground, background))
+        };
+        let (base03, base3) = (Rgb(0x00, 0x2b, 0x36), Rgb(0xfd, 0xf6, 0xe3));
+        assert_eq!(colored(&[]), Some((base03, base3)));
+        assert_eq!(
+            colored(&["--foreground", "navy"]),
+            Some((Rgb(0, 0, 128), base3))
+        );
+        assert_eq!(
+            colored(&["--background", "white"]),
+            Some((base03, Rgb::WHITE))
+        );
+        assert_eq!(colored(&["--invert"]), Some((base3, base03)));
+
+        let (dark_grey, off_white) = (Rgb(0x1c, 0x1c, 0x1c), Rgb(0xf5, 0xf5, 0xf5));
+        let dark = |extra: &[&str]| {
+            let args = ["qrterm", "--color", "always", "--theme", "dark"];
+            colors(&Options::from_iter(args.iter().chain(extra)))
+                .unwrap()
+                .map(|(foreground, background, _)| (foreground, background))
+        };
+        assert_eq!(dark(&[]), Some((off_white, dark_grey)));
+        assert_eq!(
+            dark(&["--foreground", "yellow"]),
+            Some((Rgb(255, 255, 0), dark_grey))
+        );
+        assert_eq!(
+            dark(&["--background", "navy"]),
+            Some((off_white, Rgb(0, 0, 128)))
+        );
+    }
+
+    #[test]
+    fn auto_contrast_replaces_dark_background() {
+        let colored = |extra: &[&str]| {
+            let args = ["qrterm", "--color", "always", "--foreground", "#111"];
+            colors(&Options::from_iter(args.iter().chain(extra)))
+                .unwrap()
+                .map(|(foreground, background, _)| (foreground, background))
+        };
+        let dark = Rgb(0x11, 0x11, 0x11);
+        assert_eq!(colored(&["--background", "#222"]), Some((dark, Rgb::WHITE)));
+        assert_eq!(
+            colored(&["--background", "#222", "--no-auto-contrast"]),
+            Some((dark, Rgb(0x22, 0x22, 0x22)))
+        );
+        assert_eq!(
+            colored(&["--background", "yellow"]),
+            Some((dark, Rgb(255, 255, 0)))
+        );
+    }
+
+    #[test]
+    fn contrast_check_rejects_low_contrast() {
+        let check =
+            |args: &[&str]| check_contrast(&Options::from_iter(["qrterm"].iter().chain(args)));
+        let low = [
+            "--contrast-check",
+            "--foreground",
+            "#777",
+            "--background",
+            "#999",
+        ];
+        assert!(check(&low).is_err());
+        assert!(check(&[&low[..], &["--force"]].concat()).is_ok());
+        assert!(check(&low[1..]).is_ok());
+        assert!(check(&["--contrast-check", "--foreground", "navy"]).is_ok());
+        assert!(check(&["--contrast-check"]).is_ok());
+
+        // The requested colours are checked, even though auto-contrast would
+        // replace the background, regardless of how the code is drawn.
+        let dark = [
+            "--contrast-check",
+            "--foreground",
+            "#111",
+            "--background",
+            "#222",
+        ];
+        assert!(check(&dark).is_err());
+        assert!(check(&[&dark[..], &["--format", "svg"]].concat()).is_err());
+        assert!(check(&[&dark[..], &["--charset", "ascii"]].concat()).is_err());
+
+        // A minimum contrast implies the check.
+        assert!(check(&["--foreground", "#555", "--min-contrast", "10"]).is_err());
+        assert!(check(&["--foreground", "#555", "--contrast-check"]).is_ok());
+    }
+
+    #[test]
+    fn crc_round_trips_through_scan() {
+        let options = options(&["--crc"]);
+        let inputs = prepare_inputs(&options, vec![b"hello".to_vec()]).unwrap();
+        let codes = build_codes(&options, &inputs).unwrap();
+        let scanned = decode::scan(&codes[0].1, Dot::Black).unwrap();
+        assert_eq!(scanned, b"hello~crc32:3610a686");
+        assert_eq!(crc::verify(&scanned).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn protocol_detection() {
+        let detect = |vars: &[(&str, &str)]| {
+            detect_protocol(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        assert_eq!(detect(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+        assert_eq!(
+            detect(&[("TERM", "foot"), ("KITTY_WINDOW_ID", "1")]),
+            Protocol::Kitty
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-sixel"), ("TERM_PROGRAM", "WezTerm")]),
+            Protocol::Iterm2
+        );
+        assert_eq!(detect(&[("TERM", "xterm-sixel")]), Protocol::Sixel);
+        assert_eq!(detect(&[("TERM", "foot")]), Protocol::Sixel);
+        assert_eq!(detect(&[("TERM", "mlterm")]), Protocol::Sixel);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), Protocol::Blocks);
+        assert_eq!(detect(&[]), Protocol::Blocks);
+    }
+
+    #[test]
+    fn grid_stitches_codes_side_by_side() {
+        let matrix = |args: &[&str]| {
+            let options = options(&[&["--matrix"], args].concat());
+            let inputs = resolve_inputs(&options, || unreachable!()).unwrap();
+            let mut out = Vec::new();
+            render(&options, &inputs, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // The short code is version 1, 29 dots wide with the quiet zone, and
+        // the long one version 2, 33 dots wide.
+        let long = "a".repeat(20);
+        let short = matrix(&["a"]);
+        let long_code = matrix(&[&long]);
+        let grid = matrix(&["--each-arg", "--grid", "2x1", "a", &long]);
+
+        let short = short.lines().collect::<Vec<_>>();
+        let long_code = long_code.lines().collect::<Vec<_>>();
+        assert_eq!((short.len(), long_code.len()), (29, 33));
+        let expected = long_code
+            .iter()
+            .enumerate()
+            .map(|(i, long)| {
+                let short = short.get(i).copied().unwrap_or_default();
+                format!("{:0<33}00{}\n", short, long)
+            })
+            .collect::<String>();
+        assert_eq!(grid, expected);
+    }
+
+    #[test]
+    fn grid_wraps_into_rows_and_grids() {
+        let inputs = ["a", "b", "c"].map(|data| data.as_bytes().to_vec());
+        let codes = build_codes(&options(&[]), &inputs).unwrap();
+        let (code_width, _) = codes[0].1.dimensions();
+
+        let grid = |columns, rows| {
+            layout_grid(Grid { columns, rows }, codes.clone(), Dot::White)
+                .iter()
+                .map(|page| {
+                    page.iter()
+                        .map(|(_, image)| image.dimensions().0 / code_width)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(grid(2, 2), [vec![2, 1]]);
+        assert_eq!(grid(2, 1), [vec![2], vec![1]]);
+        assert_eq!(grid(3, 1), [vec![3]]);
+
+        assert_eq!(
+            "3x2".parse::<Grid>().unwrap(),
+            Grid {
+                columns: 3,
+                rows: 2
+            }
+        );
+        for grid in ["3", "0x2", "2x", "x2", "axb"] {
+            assert!(grid.parse::<Grid>().is_err(), "{}", grid);
+        }
+    }
+
+    #[test]
+    fn encoding_report_follows_forced_mode() {
+        let report = |args: &[&str]| {
+            let mut out = Vec::new();
+            let data = b"0123456789hello";
+            write_encoding_report(&options(args), data, Version::Normal(1), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            report(&[]),
+            "numeric[0..10]: 48 bits\nbyte[10..15]: 52 bits\n"
+        );
+        assert_eq!(report(&["--mode", "byte"]), "byte[0..15]: 132 bits\n");
+    }
+
+    #[test]
+    fn outline_finder_style_requires_svg() {
+        let write = |args: &[&str]| {
+            let options = options(&[&["--finder-style", "outline"], args].concat());
+            write_output(&options, &[b"hello".to_vec()], &mut io::sink())
+        };
+        assert!(write(&["--format", "svg"]).is_ok());
+        for format in ["png", "pbm", "terminal"] {
+            assert!(write(&["--format", format]).is_err(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn split_layouts() {
+        let inputs = [vec![b'a'; 40]];
+        let split = ["--split", "--max-terminal-modules", "21"];
+
+        let stacked = build_codes(&options(&split), &inputs).unwrap();
+        assert!(stacked.len() > 1);
+
+        let row = options(&[&split[..], &["--split-layout", "row"]].concat());
+        let codes = build_codes(&row, &inputs).unwrap();
+        assert_eq!(codes.len(), 1);
+        let labels = stacked
+            .iter()
+            .map(|(label, _)| label.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes[0]
+                .0
+                .as_deref()
+                .unwrap()
+                .split_whitespace()
+                .collect::<Vec<_>>(),
+            labels,
+        );
+
+        assert_eq!("stack".parse::<SplitLayout>().unwrap(), SplitLayout::Stack);
+        assert!("column".parse::<SplitLayout>().is_err());
+    }
+
+    #[test]
+    fn compare_ec_labels_every_level() {
+        let options = options(&["--compare-ec"]);
+        let data = b"https://example.com/compare";
+        let (header, image) = build_comparison(&options, data).unwrap();
+        assert_eq!(
+            header.split_whitespace().collect::<Vec<_>>(),
+            ["L", "M", "Q", "H"]
+        );
+
+        let widths = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H]
+            .iter()
+            .map(|&ec| {
+                build_image(&options, data, Some(ec))
+                    .unwrap()
+                    .dimensions()
+                    .0
+            })
+            .collect::<Vec<_>>();
+        assert!(widths.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(widths[0] < widths[3]);
+        assert_eq!(image.dimensions().0, widths.iter().sum::<usize>() + 3 * 2);
+    }
+
+    #[test]
+    fn strict_utf8_reports_offset() {
+        let strict = options(&["--strict-utf8"]);
+        let inputs = vec![b"h\xc3\xa9llo".to_vec()];
+        assert_eq!(prepare_inputs(&strict, inputs.clone()).unwrap(), inputs);
+
+        let err = prepare_inputs(&strict, vec![b"ok".to_vec(), b"ab\xffcd".to_vec()]);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "invalid UTF-8 at byte offset 2"
+        );
+
+        let lenient = options(&[]);
+        let inputs = vec![b"ab\xffcd".to_vec()];
+        assert_eq!(prepare_inputs(&lenient, inputs.clone()).unwrap(), inputs);
+
+        // Decoded input is binary, and the offset of the decoded bytes would
+        // not match the text that was typed, so the flag is rejected.
+        for (encoding, binary) in [("hex", "ff"), ("base64", "/w==")] {
+            let encoded = options(&["--input-encoding", encoding]);
+            let inputs = vec![binary.as_bytes().to_vec()];
+            assert_eq!(prepare_inputs(&encoded, inputs.clone()).unwrap(), [[0xff]]);
+            let strict = options(&["--input-encoding", encoding, "--strict-utf8"]);
+            assert!(prepare_inputs(&strict, inputs).is_err());
+        }
+    }
+
+    #[test]
+    fn broken_pipe_detection() {
+        let broken_pipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(is_broken_pipe(&broken_pipe().into()));
+        assert!(is_broken_pipe(&QrTermError::Io(broken_pipe()).into()));
+        assert!(is_broken_pipe(
+            &anyhow::Error::from(broken_pipe()).context("writing output")
+        ));
+
+        let other = io::Error::from(io::ErrorKind::NotFound);
+        assert!(!is_broken_pipe(&other.into()));
+        assert!(!is_broken_pipe(&QrTermError::EmptyData.into()));
+        assert!(!is_broken_pipe(&anyhow!("broken pipe")));
+    }
+
+    #[test]
+    fn width_overrides_terminal_width() {
+        let centered = |width: &str| {
+            let mut out = Vec::new();
+            let options = options(&["--center", "--width", width]);
+            render(&options, &[b"hello".to_vec()], &mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+        };
+
+        // "hello" renders 29 columns wide with the default quiet zone.
+        let mut plain = Vec::new();
+        render(&options(&[]), &[b"hello".to_vec()], &mut plain).unwrap();
+        let exact = centered("29").unwrap();
+        assert_eq!(exact.as_bytes(), plain);
+        let wide = centered("41").unwrap();
+        assert!(wide
+            .lines()
+            .zip(exact.lines())
+            .all(|(wide, exact)| wide == format!("      {}", exact)));
+        assert!(centered("28").is_err());
+    }
+
+    #[test]
+    fn rtl_mirrors_centering_padding() {
+        let centered = |extra: &[&str]| {
+            let mut out = Vec::new();
+            let options = options(&[&["--center", "--width", "41"], extra].concat());
+            render(&options, &[b"hello".to_vec()], &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let mut plain = Vec::new();
+        render(&options(&[]), &[b"hello".to_vec()], &mut plain).unwrap();
+        let plain = String::from_utf8(plain).unwrap();
+        let ltr = centered(&[]);
+        let rtl = centered(&["--rtl"]);
+        assert_eq!(ltr.lines().count(), plain.lines().count());
+        for ((ltr, rtl), line) in ltr.lines().zip(rtl.lines()).zip(plain.lines()) {
+            assert_eq!(ltr, format!("      {}", line));
+            assert_eq!(rtl, format!("{}      ", line));
+        }
+
+        assert!(Options::from_iter_safe(["qrterm", "--rtl"]).is_err());
+    }
+
+    #[test]
+    fn max_terminal_modules_guard() {
+        // "hello" fits in a version 1 code, which is 21 modules wide.
+        build_image(&options(&["--max-terminal-modules", "21"]), b"hello", None).unwrap();
+
+        let err = build_image(&options(&["--max-terminal-modules", "20"]), b"hello", None);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "QR code version Normal(1) is 21 modules wide, which exceeds the maximum \
+             of 20; pass --force to render it anyway",
+        );
+
+        let forced = options(&["--max-terminal-modules", "20", "--force"]);
+        build_image(&forced, b"hello", None).unwrap();
+    }
+
+    #[test]
+    fn matrix_round_trips() {
+        let marked = options(&["--quiet-zone-char", "+"]);
+        let options = options(&["--matrix"]);
+        let image = build_image(&options, b"hello", None).unwrap();
+        let mut out = Vec::new();
+        write_image(&options, &image, &mut out).unwrap();
+        let matrix = String::from_utf8(out).unwrap();
+        assert!(matrix.lines().all(|line| line.len() == 29));
+        assert!(matrix.chars().all(|c| matches!(c, '0' | '1' | '\n')));
+        assert_eq!(parse_matrix(&options, &matrix).unwrap(), image);
+
+        let errors = [
+            ("", "matrix is empty"),
+            (
+                "10\n1\n",
+                "matrix row 2 has 1 modules, expected 2 for a square matrix",
+            ),
+            ("10\n1x\n", "invalid matrix module 'x' in row 2"),
+        ];
+        for (matrix, message) in errors {
+            let err = parse_matrix(&options, matrix).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+
+        assert!(parse_matrix(&marked, &matrix).is_err());
+    }
+
+    #[test]
+    fn watch_redraws_changed_lines() {
+        let mut options = options(&["--watch"]);
+        let mut out = Vec::new();
+        let events = line_events(&b"a\na\n\nb\n"[..]);
+        watch_events(&mut options, events, || None, &mut out).unwrap();
+
+        let mut expected = Vec::new();
+        for data in [b"a", b"b"] {
+            expected.extend_from_slice(b"\x1b[H\x1b[2J");
+            render(&options, &[data.to_vec()], &mut expected).unwrap();
+        }
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn watch_redraws_on_resize() {
+        let mut watched = options(&["--watch", "--center"]);
+        let resizes = std::cell::Cell::new(0);
+        let width = || {
+            resizes.set(resizes.get() + 1);
+            [41, 51].get(resizes.get() - 1).copied()
+        };
+        let events = vec![
+            Ok(WatchEvent::Resize),
+            Ok(WatchEvent::Data(b"hello".to_vec())),
+            Ok(WatchEvent::Resize),
+            Ok(WatchEvent::Data(b"hello".to_vec())),
+        ];
+        let mut out = Vec::new();
+        watch_events(&mut watched, events, width, &mut out).unwrap();
+        assert_eq!(watched.width, Some(51));
+        assert_eq!(resizes.get(), 2);
+
+        let mut plain = Vec::new();
+        render(&options(&[]), &[b"hello".to_vec()], &mut plain).unwrap();
+        let mut expected = String::new();
+        for padding in [6, 11] {
+            expected.push_str("\x1b[H\x1b[2J");
+            for line in String::from_utf8(plain.clone()).unwrap().lines() {
+                expected.push_str(&format!("{}{}\n", " ".repeat(padding), line));
+            }
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn watch_keeps_fixed_width_on_resize() {
+        let mut options = options(&["--watch", "--center", "--width", "41"]);
+        let events = vec![
+            Ok(WatchEvent::Data(b"hello".to_vec())),
+            Ok(WatchEvent::Resize),
+        ];
+        let mut out = Vec::new();
+        watch_events(&mut options, events, || Some(100), &mut out).unwrap();
+        assert_eq!(options.width, Some(41));
+
+        let frames = String::from_utf8(out).unwrap();
+        let frames = frames.split("\x1b[H\x1b[2J").collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1], frames[2]);
+    }
+
+    #[test]
+    fn decode_hex_and_base64_input() {
+        let decode =
+            |encoding, data: &[u8]| decode_input(encoding, data).map_err(|e| e.to_string());
+        assert_eq!(decode(InputEncoding::Raw, b" 00 ").unwrap(), b" 00 ");
+        assert_eq!(
+            decode(InputEncoding::Hex, b"00 ff\n7A").unwrap(),
+            [0x00, 0xff, 0x7a]
+        );
+        assert_eq!(
+            decode(InputEncoding::Base64, b"AP96\n").unwrap(),
+            [0x00, 0xff, 0x7a]
+        );
+
+        assert_eq!(
+            decode(InputEncoding::Hex, b"abc").unwrap_err(),
+            "invalid hex input: odd number of digits"
+        );
+        for data in [&b"zz"[..], b"+f"] {
+            assert_eq!(
+                decode(InputEncoding::Hex, data).unwrap_err(),
+                "invalid hex input: non-hex digit"
+            );
+        }
+        assert!(decode(InputEncoding::Base64, b"A")
+            .unwrap_err()
+            .starts_with("invalid base64 input: "));
+    }
+
+    #[test]
+    fn quiet_zone_and_scale_dimensions() {
+        // "hello" is encoded in a 21 module wide version 1 code.
+        let width = |args: &[&str]| {
+            build_image(&options(args), b"hello", None)
+                .unwrap()
+                .dimensions()
+                .0
+        };
+        assert_eq!(width(&[]), 29);
+        assert_eq!(width(&["--quiet-zone", "0"]), 21);
+        assert_eq!(width(&["--quiet-zone", "1", "--scale", "2"]), 46);
+    }
+}
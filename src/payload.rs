@@ -0,0 +1,324 @@
+//! Builders for well-known QR code payload formats. These take care of the
+//! escaping rules for each format, which are easy to get wrong by hand.
+
+use crate::error::{QrTermError, Result};
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The security type of a WiFi network.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WifiSecurity {
+    /// An open network without a password.
+    None,
+    /// WEP.
+    Wep,
+    /// WPA or WPA2 personal.
+    Wpa,
+    /// WPA3 personal.
+    Wpa3,
+}
+
+impl FromStr for WifiSecurity {
+    type Err = QrTermError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "nopass" | "none" | "open" => Ok(WifiSecurity::None),
+            "wep" => Ok(WifiSecurity::Wep),
+            "wpa" | "wpa2" => Ok(WifiSecurity::Wpa),
+            "wpa3" | "sae" => Ok(WifiSecurity::Wpa3),
+            _ => Err(QrTermError::InvalidOptions(format!(
+                "unknown WiFi security type '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Builds a `WIFI:` network configuration payload.
+///
+/// A password is required for all security types except
+/// [`WifiSecurity::None`], for which it is forbidden, so that the payload
+/// cannot silently describe a network that devices fail to connect to.
+pub fn wifi(
+    ssid: &str,
+    password: Option<&str>,
+    security: WifiSecurity,
+    hidden: bool,
+) -> Result<String> {
+    let kind = match security {
+        WifiSecurity::None => "nopass",
+        WifiSecurity::Wep => "WEP",
+        WifiSecurity::Wpa => "WPA",
+        WifiSecurity::Wpa3 => "SAE",
+    };
+
+    let mut payload = format!("WIFI:T:{};S:{};", kind, escape_wifi(ssid));
+    match (security, password) {
+        (WifiSecurity::None, None) => {}
+        (WifiSecurity::None, Some(_)) => {
+            return Err(QrTermError::InvalidOptions(
+                "a password is not allowed for open networks".to_owned(),
+            ))
+        }
+        (_, Some(password)) => write!(payload, "P:{};", escape_wifi(password)).unwrap(),
+        (_, None) => {
+            return Err(QrTermError::InvalidOptions(
+                "a password is required for secured networks".to_owned(),
+            ))
+        }
+    }
+    if hidden {
+        payload.push_str("H:true;");
+    }
+    payload.push(';');
+    Ok(payload)
+}
+
+/// Escapes the special characters in a `WIFI:` payload field with a
+/// backslash.
+fn escape_wifi(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Contact details for a vCard payload.
+#[derive(Clone, Debug, Default)]
+pub struct Contact<'a> {
+    /// The full name of the contact.
+    pub name: &'a str,
+    /// The email address.
+    pub email: Option<&'a str>,
+    /// The phone number.
+    pub phone: Option<&'a str>,
+    /// The organization.
+    pub org: Option<&'a str>,
+    /// The website URL.
+    pub url: Option<&'a str>,
+}
+
+/// Builds a vCard 3.0 contact payload.
+pub fn vcard(contact: &Contact) -> String {
+    let mut payload = String::from("BEGIN:VCARD\r\nVERSION:3.0\r\n");
+    let name = escape_vcard(contact.name);
+    write!(payload, "N:{}\r\nFN:{}\r\n", name, name).unwrap();
+    for (property, value) in &[
+        ("EMAIL", contact.email),
+        ("TEL", contact.phone),
+        ("ORG", contact.org),
+        ("URL", contact.url),
+    ] {
+        if let Some(value) = value {
+            write!(payload, "{}:{}\r\n", property, escape_vcard(value)).unwrap();
+        }
+    }
+    payload.push_str("END:VCARD");
+    payload
+}
+
+/// Escapes a vCard property value.
+fn escape_vcard(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds an `otpauth://totp/` URI for provisioning a time-based one-time
+/// password in an authenticator app, with the default SHA-1 algorithm.
+///
+/// The secret must be base32 encoded. Spaces are removed and it is
+/// uppercased, as secrets are commonly displayed in lowercase groups.
+pub fn otpauth(
+    issuer: &str,
+    account: &str,
+    secret: &str,
+    digits: u8,
+    period: u32,
+) -> Result<String> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let valid_secret = secret.trim_end_matches('=');
+    if valid_secret.is_empty()
+        || !valid_secret
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c))
+    {
+        return Err(QrTermError::InvalidOptions(
+            "OTP secret must be base32 encoded".to_owned(),
+        ));
+    }
+
+    Ok(format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        valid_secret,
+        percent_encode(issuer),
+        digits,
+        period,
+    ))
+}
+
+/// Builds a `mailto:` URI with an optional subject and body.
+pub fn mailto(to: &str, subject: Option<&str>, body: Option<&str>) -> String {
+    let mut payload = format!("mailto:{}", percent_encode(to).replace("%40", "@"));
+    let mut separator = '?';
+    for (field, value) in &[("subject", subject), ("body", body)] {
+        if let Some(value) = value {
+            write!(payload, "{}{}={}", separator, field, percent_encode(value)).unwrap();
+            separator = '&';
+        }
+    }
+    payload
+}
+
+/// Percent-encodes every byte of the value except for unreserved URI
+/// characters.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &byte in value.as_bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{:02X}", byte).unwrap();
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wifi_payloads() {
+        assert_eq!(
+            wifi("my;net", Some(r#"p:a"s\s"#), WifiSecurity::Wpa, false).unwrap(),
+            r#"WIFI:T:WPA;S:my\;net;P:p\:a\"s\\s;;"#,
+        );
+        assert_eq!(
+            wifi("open", None, WifiSecurity::None, true).unwrap(),
+            "WIFI:T:nopass;S:open;H:true;;",
+        );
+    }
+
+    #[test]
+    fn wifi_hidden_field() {
+        assert_eq!(
+            wifi(r#"my "net""#, Some("a;b"), WifiSecurity::Wpa3, true).unwrap(),
+            r#"WIFI:T:SAE;S:my \"net\";P:a\;b;H:true;;"#,
+        );
+        assert!(!wifi("net", Some("pass"), WifiSecurity::Wpa, false)
+            .unwrap()
+            .contains("H:"));
+    }
+
+    #[test]
+    fn wifi_password_matches_security() {
+        for security in [WifiSecurity::Wep, WifiSecurity::Wpa, WifiSecurity::Wpa3] {
+            assert!(wifi("net", Some("secret"), security, false).is_ok());
+            assert!(matches!(
+                wifi("net", None, security, false),
+                Err(QrTermError::InvalidOptions(_))
+            ));
+        }
+        assert!(wifi("net", None, WifiSecurity::None, false).is_ok());
+        assert!(matches!(
+            wifi("net", Some("secret"), WifiSecurity::None, false),
+            Err(QrTermError::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn parse_wifi_security() {
+        for &(input, expected) in &[
+            ("nopass", WifiSecurity::None),
+            ("Open", WifiSecurity::None),
+            ("WEP", WifiSecurity::Wep),
+            ("wpa", WifiSecurity::Wpa),
+            ("Wpa", WifiSecurity::Wpa),
+            ("WPA2", WifiSecurity::Wpa),
+            ("wpa2", WifiSecurity::Wpa),
+            ("SAE", WifiSecurity::Wpa3),
+        ] {
+            assert_eq!(input.parse::<WifiSecurity>().unwrap(), expected);
+        }
+        assert!("wpa4".parse::<WifiSecurity>().is_err());
+    }
+
+    #[test]
+    fn vcard_payload() {
+        let contact = Contact {
+            name: "Doe, Jane",
+            email: Some("jane@example.com"),
+            org: Some("A;B\r\nC"),
+            ..Contact::default()
+        };
+        assert_eq!(
+            vcard(&contact),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\n\
+             N:Doe\\, Jane\r\nFN:Doe\\, Jane\r\n\
+             EMAIL:jane@example.com\r\n\
+             ORG:A\\;B\\nC\r\n\
+             END:VCARD",
+        );
+    }
+
+    #[test]
+    fn otpauth_payload() {
+        assert_eq!(
+            otpauth(
+                "ACME Co",
+                "jane@example.com",
+                "jbsw y3dp ehpk 3pxp==",
+                6,
+                30
+            )
+            .unwrap(),
+            "otpauth://totp/ACME%20Co:jane%40example.com\
+             ?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&digits=6&period=30",
+        );
+        for secret in ["", "====", "JBSW1"] {
+            assert!(
+                otpauth("ACME", "jane", secret, 6, 30).is_err(),
+                "{}",
+                secret
+            );
+        }
+    }
+
+    #[test]
+    fn mailto_payload() {
+        assert_eq!(
+            mailto("jane@example.com", None, None),
+            "mailto:jane@example.com"
+        );
+        assert_eq!(
+            mailto("jane@example.com", Some("Hi there"), Some("a&b")),
+            "mailto:jane@example.com?subject=Hi%20there&body=a%26b",
+        );
+        assert_eq!(
+            mailto("jane@example.com", None, Some("body")),
+            "mailto:jane@example.com?body=body",
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! High level rendering of data into QR code images.
+
+use crate::color::Rgb;
+use crate::encode::{self, EncodeOptions};
+use crate::error::{QrTermError, Result};
+use crate::image::{Dot, Image};
+use qrcode::render::Renderer;
+use qrcode::types::Mode;
+use qrcode::EcLevel;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::str::FromStr;
+
+/// When to colour rendered QR codes with ANSI escape sequences.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorMode {
+    /// Colour the output when writing to a terminal and `NO_COLOR` is unset.
+    Auto,
+    /// Always colour the output.
+    Always,
+    /// Never colour the output.
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = QrTermError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(QrTermError::InvalidOptions(format!(
+                "invalid colour mode '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Options for rendering data as QR codes.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Options for encoding the data into a QR code.
+    pub encode: EncodeOptions,
+    /// The colour of dark modules.
+    pub foreground: Rgb,
+    /// The colour of light modules, including the quiet zone.
+    pub background: Rgb,
+    /// When to colour the output.
+    pub color: ColorMode,
+    /// The width of the light border around the QR code in modules.
+    pub quiet_zone: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            encode: EncodeOptions::default(),
+            foreground: Rgb::BLACK,
+            background: Rgb::WHITE,
+            color: ColorMode::Auto,
+            quiet_zone: 4,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Returns a builder for constructing validated render options.
+    pub fn builder() -> RenderOptionsBuilder {
+        RenderOptionsBuilder::default()
+    }
+}
+
+/// A builder for [`RenderOptions`] that validates the combination of options
+/// when building.
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptionsBuilder {
+    options: RenderOptions,
+    no_color: bool,
+    quiet_zone: Option<i32>,
+}
+
+impl RenderOptionsBuilder {
+    /// Sets the minimum error correction level.
+    pub fn ec_level(mut self, ec_level: EcLevel) -> Self {
+        self.options.encode.ec_level = ec_level;
+        self
+    }
+
+    /// Sets whether to raise the error correction level when the selected
+    /// version has spare capacity for it.
+    pub fn boost_ecc(mut self, boost_ecc: bool) -> Self {
+        self.options.encode.boost_ecc = boost_ecc;
+        self
+    }
+
+    /// Sets the smallest QR code version to use.
+    pub fn min_version(mut self, min_version: Option<i16>) -> Self {
+        self.options.encode.min_version = min_version;
+        self
+    }
+
+    /// Sets the exact QR code version to use.
+    pub fn version(mut self, version: Option<i16>) -> Self {
+        self.options.encode.version = version;
+        self
+    }
+
+    /// Sets the mode to encode all of the data in.
+    pub fn mode(mut self, mode: Option<Mode>) -> Self {
+        self.options.encode.mode = mode;
+        self
+    }
+
+    /// Sets the colour of dark modules.
+    pub fn foreground(mut self, foreground: Rgb) -> Self {
+        self.options.foreground = foreground;
+        self
+    }
+
+    /// Sets the colour of light modules.
+    pub fn background(mut self, background: Rgb) -> Self {
+        self.options.background = background;
+        self
+    }
+
+    /// Sets when to colour the output.
+    pub fn color(mut self, color: ColorMode) -> Self {
+        self.options.color = color;
+        self
+    }
+
+    /// Sets whether the `NO_COLOR` environment variable is set, which disables
+    /// colours in [`ColorMode::Auto`].
+    pub fn no_color(mut self, no_color: bool) -> Self {
+        self.no_color = no_color;
+        self
+    }
+
+    /// Sets the width of the quiet zone in modules. This is signed so that
+    /// margins from configuration files can be passed as is and are validated
+    /// when building.
+    pub fn quiet_zone(mut self, quiet_zone: i32) -> Self {
+        self.quiet_zone = Some(quiet_zone);
+        self
+    }
+
+    /// Validates and builds the render options.
+    ///
+    /// Returns [`QrTermError::InvalidVersion`] if the exact or minimum version
+    /// is not between 1 and 40, and [`QrTermError::InvalidOptions`] if both
+    /// an exact and minimum version are specified, if the quiet zone is
+    /// negative, if the foreground and background colours are the same, since
+    /// the code would be unscannable, or if colours are forced while
+    /// `NO_COLOR` is set.
+    pub fn build(self) -> Result<RenderOptions> {
+        let mut options = self.options;
+        if let Some(quiet_zone) = self.quiet_zone {
+            options.quiet_zone = u32::try_from(quiet_zone).map_err(|_| {
+                QrTermError::InvalidOptions(format!(
+                    "quiet zone must not be negative, but is {}",
+                    quiet_zone
+                ))
+            })?;
+        }
+        let encode = &options.encode;
+        for version in encode.version.iter().chain(&encode.min_version) {
+            if !(1..=40).contains(version) {
+                return Err(QrTermError::InvalidVersion);
+            }
+        }
+        if encode.version.is_some() && encode.min_version.is_some() {
+            return Err(QrTermError::InvalidOptions(
+                "an exact version conflicts with a minimum version".to_owned(),
+            ));
+        }
+        if options.foreground == options.background {
+            return Err(QrTermError::InvalidOptions(
+                "foreground and background colours must differ".to_owned(),
+            ));
+        }
+        if options.color == ColorMode::Always && self.no_color {
+            return Err(QrTermError::InvalidOptions(
+                "colours cannot be forced while NO_COLOR is set".to_owned(),
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// Encodes data into a QR code and renders it as an image of dots, surrounded
+/// by the quiet zone.
+pub fn render_image(data: &[u8], options: &RenderOptions) -> Result<Image> {
+    let code = encode::encode(data, &options.encode)?;
+    Ok(Renderer::<Dot>::new(&code.to_colors(), code.width(), options.quiet_zone).build())
+}
+
+/// Encodes data into a QR code and renders it as a string of unicode block
+/// characters, with a newline at the end of every line.
+///
+/// The string is not coloured, so it renders correctly with a dark font on a
+/// light background. See [`Image::write_ansi_to`] for coloured output.
+pub fn render_to_string(data: &[u8], options: &RenderOptions) -> Result<String> {
+    let image = render_image(data, options)?;
+    let mut buffer = Vec::new();
+    image.write_to(&mut buffer)?;
+    Ok(String::from_utf8(buffer).expect("rendered image is valid UTF-8"))
+}
+
+/// Renders each item as a QR code to the writer, separating consecutive codes
+/// with a blank line, and aborting on the first error.
+///
+/// Items are encoded and written one at a time, so the images for the whole
+/// batch are never held in memory at once. See [`render_batch_with`] for
+/// continuing past items that fail to render.
+pub fn render_batch<I, W>(items: I, options: &RenderOptions, out: &mut W) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+    W: Write,
+{
+    render_batch_with(items, options, out, |_, err| Err(err))
+}
+
+/// Renders each item as a QR code to the writer like [`render_batch`], calling
+/// `on_error` with the index and error of each item that fails to encode.
+///
+/// The batch is aborted if `on_error` returns an error, otherwise the item is
+/// skipped and rendering continues with the next one. Errors writing to `out`
+/// always abort the batch.
+pub fn render_batch_with<I, W, F>(
+    items: I,
+    options: &RenderOptions,
+    out: &mut W,
+    mut on_error: F,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+    W: Write,
+    F: FnMut(usize, QrTermError) -> Result<()>,
+{
+    let mut first = true;
+    for (i, data) in items.into_iter().enumerate() {
+        let image = match render_image(&data, options) {
+            Ok(image) => image,
+            Err(err) => {
+                on_error(i, err)?;
+                continue;
+            }
+        };
+        if !first {
+            writeln!(out)?;
+        }
+        image.write_to(out)?;
+        first = false;
+    }
+    Ok(())
+}
+
+/// Encodes data into a QR code and renders it to raw RGBA pixels, returning
+/// the pixel buffer along with its width and height in pixels.
+///
+/// The buffer is laid out in row-major order with four bytes per pixel in
+/// red, green, blue and alpha order, with the alpha always fully opaque. Each
+/// module, including the quiet zone, is drawn as a square of `scale` by
+/// `scale` pixels, where a `scale` of 0 is treated as 1.
+pub fn render_rgba(
+    data: &[u8],
+    options: &RenderOptions,
+    scale: u32,
+) -> Result<(Vec<u8>, u32, u32)> {
+    let image = render_image(data, options)?;
+    let (columns, _) = image.dimensions();
+    let dots = image.to_dots();
+    let rows = dots.len().checked_div(columns).unwrap_or(0);
+
+    let scale = scale.max(1) as usize;
+    let (width, height) = (columns * scale, rows * scale);
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in dots.chunks(columns.max(1)) {
+        let mut line = Vec::with_capacity(width * 4);
+        for dot in row {
+            let Rgb(r, g, b) = match dot {
+                Dot::Black => options.foreground,
+                Dot::White => options.background,
+            };
+            for _ in 0..scale {
+                line.extend_from_slice(&[r, g, b, 0xff]);
+            }
+        }
+        for _ in 0..scale {
+            pixels.extend_from_slice(&line);
+        }
+    }
+
+    Ok((pixels, width as u32, height as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_accepts_valid_options() {
+        let options = RenderOptions::builder()
+            .ec_level(EcLevel::Q)
+            .min_version(Some(5))
+            .foreground(Rgb(1, 2, 3))
+            .build()
+            .unwrap();
+        assert_eq!(options.encode.ec_level, EcLevel::Q);
+        assert_eq!(options.encode.min_version, Some(5));
+        assert_eq!(options.foreground, Rgb(1, 2, 3));
+        assert_eq!(options.background, Rgb::WHITE);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_options() {
+        for version in [0, 41] {
+            let exact = RenderOptions::builder().version(Some(version)).build();
+            assert!(matches!(exact, Err(QrTermError::InvalidVersion)));
+            let min = RenderOptions::builder().min_version(Some(version)).build();
+            assert!(matches!(min, Err(QrTermError::InvalidVersion)));
+        }
+
+        let conflicting = RenderOptions::builder()
+            .version(Some(2))
+            .min_version(Some(1))
+            .build();
+        assert!(matches!(conflicting, Err(QrTermError::InvalidOptions(_))));
+
+        let negative_margin = RenderOptions::builder().quiet_zone(-1).build();
+        assert!(matches!(
+            negative_margin,
+            Err(QrTermError::InvalidOptions(_))
+        ));
+
+        let same_colours = RenderOptions::builder()
+            .foreground(Rgb::WHITE)
+            .background(Rgb::WHITE)
+            .build();
+        assert!(matches!(same_colours, Err(QrTermError::InvalidOptions(_))));
+    }
+
+    #[test]
+    fn builder_color_mode_with_no_color() {
+        for mode in [ColorMode::Auto, ColorMode::Never] {
+            let options = RenderOptions::builder()
+                .color(mode)
+                .no_color(true)
+                .build()
+                .unwrap();
+            assert_eq!(options.color, mode);
+        }
+        assert!(RenderOptions::builder()
+            .color(ColorMode::Always)
+            .build()
+            .is_ok());
+
+        let forced = RenderOptions::builder()
+            .color(ColorMode::Always)
+            .no_color(true)
+            .build();
+        assert!(matches!(forced, Err(QrTermError::InvalidOptions(_))));
+
+        assert_eq!("never".parse::<ColorMode>().unwrap(), ColorMode::Never);
+        assert!("sometimes".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn render_to_string_lines() {
+        let options = RenderOptions::default();
+        let output = render_to_string(b"hello", &options).unwrap();
+        // A 29 dot high image takes 14 full lines and a final half line.
+        assert_eq!(output.lines().count(), 15);
+        assert!(output.lines().all(|line| line.chars().count() == 29));
+        assert!(output.ends_with('\n'));
+
+        let err = render_to_string(b"", &options).err();
+        assert!(matches!(err, Some(QrTermError::EmptyData)));
+    }
+
+    #[test]
+    fn render_rgba_pixel_offsets() {
+        let options = RenderOptions::builder()
+            .foreground(Rgb(1, 2, 3))
+            .background(Rgb(4, 5, 6))
+            .build()
+            .unwrap();
+        let (pixels, width, height) = render_rgba(b"hello", &options, 3).unwrap();
+        assert_eq!((width, height), (29 * 3, 29 * 3));
+        assert_eq!(pixels.len(), 29 * 3 * 29 * 3 * 4);
+
+        let pixel = |x: usize, y: usize| &pixels[(x + y * width as usize) * 4..][..4];
+        // The quiet zone is 4 modules wide, so the finder pattern starts at
+        // module (4, 4), with a light ring at module (5, 5).
+        assert_eq!(pixel(0, 0), [4, 5, 6, 0xff]);
+        assert_eq!(pixel(11, 11), [4, 5, 6, 0xff]);
+        for (x, y) in [(12, 12), (14, 12), (12, 14), (14, 14)] {
+            assert_eq!(pixel(x, y), [1, 2, 3, 0xff]);
+        }
+        assert_eq!(pixel(15, 15), [4, 5, 6, 0xff]);
+    }
+
+    #[test]
+    fn render_with_quiet_zone() {
+        let width = |quiet_zone| {
+            let options = RenderOptions::builder()
+                .quiet_zone(quiet_zone)
+                .build()
+                .unwrap();
+            assert_eq!(options.quiet_zone, quiet_zone as u32);
+            render_image(b"hello", &options).unwrap().dimensions().0
+        };
+        assert_eq!(width(0), 21);
+        assert_eq!(width(2), 25);
+        assert_eq!(RenderOptions::builder().build().unwrap().quiet_zone, 4);
+    }
+
+    #[test]
+    fn render_rgba_zero_scale_is_one() {
+        let options = RenderOptions::default();
+        let (pixels, width, height) = render_rgba(b"hello", &options, 0).unwrap();
+        assert_eq!((width, height), (29, 29));
+        assert_eq!(pixels.len(), 29 * 29 * 4);
+    }
+
+    #[test]
+    fn render_batch_writes_items_in_order() {
+        let options = RenderOptions::default();
+        let items = [b"one".to_vec(), b"two".to_vec(), b"three".to_vec()];
+        let mut out = Vec::new();
+        render_batch(items.clone(), &options, &mut out).unwrap();
+
+        let expected = items
+            .iter()
+            .map(|item| render_to_string(item, &options).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn render_batch_with_skips_failed_items() {
+        let options = RenderOptions::default();
+        let items = vec![b"one".to_vec(), Vec::new(), b"three".to_vec()];
+        let mut failed = Vec::new();
+        let mut out = Vec::new();
+        render_batch_with(items, &options, &mut out, |i, err| {
+            assert!(matches!(err, QrTermError::EmptyData));
+            failed.push(i);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(failed, [1]);
+        let expected = [
+            render_to_string(b"one", &options).unwrap(),
+            render_to_string(b"three", &options).unwrap(),
+        ]
+        .join("\n");
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let empty = vec![Vec::new()];
+        let err = render_batch(empty, &options, &mut Vec::new()).err();
+        assert!(matches!(err, Some(QrTermError::EmptyData)));
+    }
+}
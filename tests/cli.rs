@@ -0,0 +1,28 @@
+//! Command line tests running the `qrterm` binary and inspecting its output.
+
+use std::process::{Command, Output, Stdio};
+
+/// Runs `qrterm` with the arguments and no standard input.
+fn qrterm(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_qrterm"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn quiet_suppresses_warnings_and_scan_test() {
+    // A bare domain warns, and the scan test prints its result.
+    let args = ["--color", "never", "--scan-test", "example.com"];
+    let loud = qrterm(&args);
+    assert!(loud.status.success());
+    let stderr = String::from_utf8(loud.stderr).unwrap();
+    assert!(stderr.contains("domain without a scheme"), "{}", stderr);
+    assert!(stderr.contains("scan test: OK"), "{}", stderr);
+
+    let quiet = qrterm(&[&["--quiet"], &args[..]].concat());
+    assert!(quiet.status.success());
+    assert_eq!(String::from_utf8(quiet.stderr).unwrap(), "");
+    assert_eq!(quiet.stdout, loud.stdout);
+}
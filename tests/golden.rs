@@ -0,0 +1,59 @@
+//! Golden file tests comparing rendered QR codes against checked in fixtures.
+//!
+//! Set `UPDATE_GOLDEN=1` to overwrite the fixtures with the current output
+//! after an intentional change to the rendering.
+
+use qrterm::{render_to_string, RenderOptions};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+/// Asserts that the output matches the contents of the named fixture in
+/// `tests/fixtures`, panicking with a line by line diff when it does not.
+fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+    if expected == actual {
+        return;
+    }
+
+    let (expected, actual) = (
+        expected.lines().collect::<Vec<_>>(),
+        actual.lines().collect::<Vec<_>>(),
+    );
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e == a {
+            writeln!(diff, " {:>3} |{}", i + 1, e.unwrap()).unwrap();
+            continue;
+        }
+        if let Some(e) = e {
+            writeln!(diff, "-{:>3} |{}", i + 1, e).unwrap();
+        }
+        if let Some(a) = a {
+            writeln!(diff, "+{:>3} |{}", i + 1, a).unwrap();
+        }
+    }
+    panic!(
+        "output does not match {} (- expected, + actual):\n{}",
+        path.display(),
+        diff,
+    );
+}
+
+#[test]
+fn hello_world_half_blocks() {
+    let output = render_to_string(b"hello world", &RenderOptions::default()).unwrap();
+    assert_golden("hello_half.txt", &output);
+}